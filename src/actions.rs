@@ -1,150 +1,185 @@
-use crate::{
-    player::{DealDamageEvent, DieEvent},
-    GameConfiguration, GameState,
-};
+use crate::input_map::Action::{MoveDown, MoveLeft, MoveRight, MoveUp};
+use crate::input_map::{ActiveGamepad, AimMode, InputMap, Inputs};
+use crate::player::{MainCamera, Player};
+use crate::utils::project;
+use crate::GameState;
+use bevy::input::InputSystem;
 use bevy::prelude::*;
 
 pub struct ActionsPlugin;
 
-// This plugin listens for keyboard input and converts the input into Actions
+// This plugin listens for keyboard, mouse and gamepad input and converts the input into Actions
+// through the player's InputMap. It also follows gamepads being plugged in and out.
 // Actions can then be used as a resource in other systems to act on the player input.
 impl Plugin for ActionsPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<Actions>()
+        app.add_system_to_stage(CoreStage::PreUpdate, track_gamepads.after(InputSystem))
             .add_system_set(
-                SystemSet::on_update(GameState::Playing).with_system(set_movement_actions.system()),
-            )
-            .insert_resource(GameConfiguration { scale: 3. })
-            .add_event::<DealDamageEvent>()
-            .add_event::<DieEvent>();
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(set_movement_actions.system())
+                    .with_system(set_aim_actions.system()),
+            );
     }
 }
 
 #[derive(Default)]
 pub struct Actions {
     pub player_movement: Option<Vec2>,
+    /// Normalized direction from the player to where they aim, `None` while they don't
+    pub aim_direction: Option<Vec2>,
 }
 
-fn set_movement_actions(mut actions: ResMut<Actions>, keyboard_input: Res<Input<KeyCode>>) {
-    if GameControl::Up.just_released(&keyboard_input)
-        || GameControl::Up.pressed(&keyboard_input)
-        || GameControl::Left.just_released(&keyboard_input)
-        || GameControl::Left.pressed(&keyboard_input)
-        || GameControl::Down.just_released(&keyboard_input)
-        || GameControl::Down.pressed(&keyboard_input)
-        || GameControl::Right.just_released(&keyboard_input)
-        || GameControl::Right.pressed(&keyboard_input)
+fn track_gamepads(mut events: EventReader<GamepadEvent>, mut active: ResMut<ActiveGamepad>) {
+    for GamepadEvent(gamepad, event) in events.iter() {
+        match event {
+            GamepadEventType::Connected => {
+                info!("{:?} connected", gamepad);
+                active.connected.push(*gamepad);
+                if active.gamepad.is_none() {
+                    active.gamepad = Some(*gamepad);
+                }
+            }
+            GamepadEventType::Disconnected => {
+                info!("{:?} disconnected", gamepad);
+                active.connected.retain(|connected| connected != gamepad);
+                if active.gamepad == Some(*gamepad) {
+                    active.gamepad = active.connected.first().copied();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The left stick of the active gamepad drives the movement when it is out of its deadzone,
+/// otherwise the digital inputs (keys, D-pad) do
+#[allow(clippy::too_many_arguments)]
+fn set_movement_actions(
+    mut actions: ResMut<Actions>,
+    mut digital_movement: Local<Option<Vec2>>,
+    input_map: Res<InputMap>,
+    active: Res<ActiveGamepad>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+) {
+    let inputs = Inputs {
+        keys: &keyboard_input,
+        mouse: &mouse_input,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad: active.gamepad,
+    };
+    let pressed = |action| input_map.pressed(action, inputs);
+    let just_pressed = |action| input_map.just_pressed(action, inputs);
+    let just_released = |action| input_map.just_released(action, inputs);
+
+    if [MoveUp, MoveDown, MoveLeft, MoveRight]
+        .iter()
+        .any(|&action| just_released(action) || pressed(action))
     {
         let mut player_movement = Vec2::ZERO;
 
-        if GameControl::Up.just_released(&keyboard_input)
-            || GameControl::Down.just_released(&keyboard_input)
-        {
-            if GameControl::Up.pressed(&keyboard_input) {
+        if just_released(MoveUp) || just_released(MoveDown) {
+            if pressed(MoveUp) {
                 player_movement.y = 1.;
-            } else if GameControl::Down.pressed(&keyboard_input) {
+            } else if pressed(MoveDown) {
                 player_movement.y = -1.;
             } else {
                 player_movement.y = 0.;
             }
-        } else if GameControl::Up.just_pressed(&keyboard_input) {
+        } else if just_pressed(MoveUp) {
             player_movement.y = 1.;
-        } else if GameControl::Down.just_pressed(&keyboard_input) {
+        } else if just_pressed(MoveDown) {
             player_movement.y = -1.;
         } else {
-            player_movement.y = actions.player_movement.unwrap_or(Vec2::ZERO).y;
+            player_movement.y = digital_movement.unwrap_or(Vec2::ZERO).y;
         }
 
-        if GameControl::Right.just_released(&keyboard_input)
-            || GameControl::Left.just_released(&keyboard_input)
-        {
-            if GameControl::Right.pressed(&keyboard_input) {
+        if just_released(MoveRight) || just_released(MoveLeft) {
+            if pressed(MoveRight) {
                 player_movement.x = 1.;
-            } else if GameControl::Left.pressed(&keyboard_input) {
+            } else if pressed(MoveLeft) {
                 player_movement.x = -1.;
             } else {
                 player_movement.x = 0.;
             }
-        } else if GameControl::Right.just_pressed(&keyboard_input) {
+        } else if just_pressed(MoveRight) {
             player_movement.x = 1.;
-        } else if GameControl::Left.just_pressed(&keyboard_input) {
+        } else if just_pressed(MoveLeft) {
             player_movement.x = -1.;
         } else {
-            player_movement.x = actions.player_movement.unwrap_or(Vec2::ZERO).x;
+            player_movement.x = digital_movement.unwrap_or(Vec2::ZERO).x;
         }
 
         if player_movement != Vec2::ZERO {
             player_movement = player_movement.normalize();
-            actions.player_movement = Some(player_movement);
+            *digital_movement = Some(player_movement);
         }
     } else {
-        actions.player_movement = None;
+        *digital_movement = None;
     }
-}
 
-enum GameControl {
-    Up,
-    Down,
-    Left,
-    Right,
+    let stick = active.gamepad.and_then(|gamepad| {
+        let axis = |axis| gamepad_axes.get(GamepadAxis(gamepad, axis)).unwrap_or(0.);
+        input_map.stick.apply(Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        ))
+    });
+    actions.player_movement = stick.or(*digital_movement);
 }
 
-impl GameControl {
-    fn just_released(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
-        match self {
-            GameControl::Up => {
-                keyboard_input.just_released(KeyCode::W)
-                    || keyboard_input.just_released(KeyCode::Up)
-            }
-            GameControl::Down => {
-                keyboard_input.just_released(KeyCode::S)
-                    || keyboard_input.just_released(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.just_released(KeyCode::A)
-                    || keyboard_input.just_released(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.just_released(KeyCode::D)
-                    || keyboard_input.just_released(KeyCode::Right)
-            }
-        }
+/// The right stick aims while it is tilted, otherwise the mouse cursor does once it has moved
+/// With `AimMode::Auto` the weapons pick their targets on their own
+#[allow(clippy::too_many_arguments)]
+fn set_aim_actions(
+    mut actions: ResMut<Actions>,
+    mut last_cursor: Local<Option<Vec2>>,
+    mut mouse_aiming: Local<bool>,
+    input_map: Res<InputMap>,
+    active: Res<ActiveGamepad>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    windows: Option<Res<Windows>>,
+    camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let cursor = windows
+        .as_ref()
+        .and_then(|windows| windows.get_primary())
+        .and_then(|window| window.cursor_position());
+    if cursor.is_some() && cursor != *last_cursor {
+        *mouse_aiming = true;
     }
+    *last_cursor = cursor;
 
-    fn pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
-        match self {
-            GameControl::Up => {
-                keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up)
-            }
-            GameControl::Down => {
-                keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right)
-            }
-        }
+    let stick = active.gamepad.and_then(|gamepad| {
+        let axis = |axis| gamepad_axes.get(GamepadAxis(gamepad, axis)).unwrap_or(0.);
+        input_map.stick.apply(Vec2::new(
+            axis(GamepadAxisType::RightStickX),
+            axis(GamepadAxisType::RightStickY),
+        ))
+    });
+    if stick.is_some() {
+        *mouse_aiming = false;
     }
 
-    fn just_pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
-        match self {
-            GameControl::Up => {
-                keyboard_input.just_pressed(KeyCode::W) || keyboard_input.just_pressed(KeyCode::Up)
-            }
-            GameControl::Down => {
-                keyboard_input.just_pressed(KeyCode::S)
-                    || keyboard_input.just_pressed(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.just_pressed(KeyCode::A)
-                    || keyboard_input.just_pressed(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.just_pressed(KeyCode::D)
-                    || keyboard_input.just_pressed(KeyCode::Right)
-            }
+    let aim = match (stick, &windows) {
+        (Some(stick), _) => Some(stick),
+        (None, Some(windows)) if *mouse_aiming => {
+            let player = match player.get_single() {
+                Ok(player) => player.translation.truncate(),
+                Err(_) => return,
+            };
+            project(windows, &camera).map(|target| target - player)
         }
-    }
+        _ => None,
+    };
+
+    actions.aim_direction = match input_map.aim {
+        AimMode::Auto => None,
+        AimMode::Manual => aim
+            .map(Vec2::normalize_or_zero)
+            .filter(|aim| *aim != Vec2::ZERO),
+    };
 }
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::archetype::EnemyArchetypes;
+use crate::enemy::Dead;
+use crate::loading::DataAssets;
+use crate::player::{Health, InvincibilityFrames};
+use crate::timestep::{FixedTimestepAppExt, SimTime};
+use benimator::{Play, SpriteSheetAnimation};
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+pub struct AnimationLibraryPlugin;
+
+/// Whether an animation loops or stops on its last frame
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimationMode {
+    Repeat,
+    Once,
+}
+
+impl Default for AnimationMode {
+    fn default() -> Self {
+        AnimationMode::Repeat
+    }
+}
+
+/// Inclusive range of atlas indices, each shown for `frame_duration` seconds
+#[derive(Deserialize, Clone, Debug)]
+pub struct AnimationDef {
+    pub frames: (usize, usize),
+    pub frame_duration: f32,
+    #[serde(default)]
+    pub mode: AnimationMode,
+}
+
+impl AnimationDef {
+    pub fn to_animation(&self) -> SpriteSheetAnimation {
+        let animation = SpriteSheetAnimation::from_range(
+            self.frames.0..=self.frames.1,
+            Duration::from_secs_f32(self.frame_duration),
+        );
+        match self.mode {
+            AnimationMode::Repeat => animation.repeat(),
+            AnimationMode::Once => animation.once(),
+        }
+    }
+}
+
+/// Named animations that don't belong to an enemy archetype, like the player and the weapon effects
+/// Loaded from `*.animations.ron` files, see `assets/data/default.animations.ron`
+#[derive(Deserialize, TypeUuid)]
+#[serde(transparent)]
+#[uuid = "0f5c8e57-2a43-4d2b-93b1-7d6e4f1a9c38"]
+pub struct AnimationDefs(pub HashMap<String, AnimationDef>);
+
+#[derive(Default)]
+pub struct AnimationDefsLoader;
+
+impl AssetLoader for AnimationDefsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let animations: AnimationDefs = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(animations));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["animations.ron"]
+    }
+}
+
+/// Name of an animation of a character in the `AnimationLibrary`, like "necromancer_walk"
+pub fn archetype_animation(archetype: &str, animation: &str) -> String {
+    format!("{}_{}", archetype, animation)
+}
+
+/// Shared handles of every animation, keyed by name
+/// Entities playing the same animation share its handle, instead of adding a copy every time
+#[derive(Default)]
+pub struct AnimationLibrary(HashMap<String, Handle<SpriteSheetAnimation>>);
+
+impl AnimationLibrary {
+    /// The animation called `name`, or a handle to nothing if there is none
+    pub fn get(&self, name: &str) -> Handle<SpriteSheetAnimation> {
+        match self.0.get(name) {
+            Some(handle) => handle.clone(),
+            None => {
+                warn!("Unknown animation '{}'", name);
+                Handle::default()
+            }
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Adds the animation, or replaces it in place so that the entities playing it pick it up
+    fn set(
+        &mut self,
+        name: String,
+        animation: SpriteSheetAnimation,
+        animations: &mut Assets<SpriteSheetAnimation>,
+    ) {
+        match self.0.get(&name) {
+            Some(handle) => {
+                animations.set_untracked(handle.id, animation);
+            }
+            None => {
+                self.0.insert(name, animations.add(animation));
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharacterState {
+    Idle,
+    Walk,
+    Hurt,
+    Attack,
+    Death,
+}
+
+impl CharacterState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CharacterState::Idle => "idle",
+            CharacterState::Walk => "walk",
+            CharacterState::Hurt => "hurt",
+            CharacterState::Attack => "attack",
+            CharacterState::Death => "death",
+        }
+    }
+}
+
+/// Plays the animation of what the character is doing, looked up in the `AnimationLibrary`
+/// as "<name>_<state>", like "necromancer_walk"
+/// Movement and attacks are reported by the gameplay systems, the character is hurt
+/// during its `InvincibilityFrames` and dead once it is `Dead` or out of `Health`
+#[derive(Component)]
+pub struct CharacterAnimator {
+    pub name: String,
+    pub moving: bool,
+    pub hurt: bool,
+    pub dead: bool,
+    /// Seconds left of the attack animation
+    pub attacking: f32,
+    state: Option<CharacterState>,
+}
+
+impl CharacterAnimator {
+    pub fn new(name: &str) -> Self {
+        CharacterAnimator {
+            name: name.to_string(),
+            moving: false,
+            hurt: false,
+            dead: false,
+            attacking: 0.,
+            state: None,
+        }
+    }
+
+    /// State of the animation being played, `None` until the first tick
+    pub fn state(&self) -> Option<CharacterState> {
+        self.state
+    }
+
+    pub fn animation(&self, state: CharacterState) -> String {
+        archetype_animation(&self.name, state.name())
+    }
+
+    /// The state to play: death, or else the first one that applies and has an animation
+    /// among hurt, attack, walk and idle
+    pub fn next_state(&self, has_animation: impl Fn(CharacterState) -> bool) -> CharacterState {
+        if self.dead {
+            return CharacterState::Death;
+        }
+        [
+            (self.hurt, CharacterState::Hurt),
+            (self.attacking > 0., CharacterState::Attack),
+            (self.moving, CharacterState::Walk),
+        ]
+        .into_iter()
+        .filter(|(applies, _)| *applies)
+        .map(|(_, state)| state)
+        .find(|state| has_animation(*state))
+        .unwrap_or(CharacterState::Idle)
+    }
+}
+
+/// This plugin builds the `AnimationLibrary` once the data files are loaded,
+/// from the named animations and the animations of every enemy archetype,
+/// and switches the animations of the characters as their state changes
+/// With the `dev` feature, changes to the files are applied to the animations being played
+impl Plugin for AnimationLibraryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<AnimationDefs>()
+            .init_asset_loader::<AnimationDefsLoader>()
+            .init_resource::<AnimationLibrary>()
+            .add_system(build_animation_library)
+            .add_fixed_system_set(SystemSet::new().with_system(animate_characters));
+    }
+}
+
+fn build_animation_library(
+    data: Option<Res<DataAssets>>,
+    defs: Res<Assets<AnimationDefs>>,
+    archetypes: Res<Assets<EnemyArchetypes>>,
+    mut def_events: EventReader<AssetEvent<AnimationDefs>>,
+    mut archetype_events: EventReader<AssetEvent<EnemyArchetypes>>,
+    mut library: ResMut<AnimationLibrary>,
+    mut animations: ResMut<Assets<SpriteSheetAnimation>>,
+) {
+    // only inserted at the end of the loading state
+    let data = match data {
+        Some(data) => data,
+        None => return,
+    };
+    let reloaded = def_events.iter().count() + archetype_events.iter().count() > 0;
+    if !library.is_empty() && !reloaded {
+        return;
+    }
+
+    if let Some(defs) = defs.get(&data.animations) {
+        for (name, def) in defs.0.iter() {
+            library.set(name.clone(), def.to_animation(), &mut animations);
+        }
+    }
+    if let Some(archetypes) = archetypes.get(&data.enemies) {
+        for (name, archetype) in archetypes.0.iter() {
+            let idle = archetype.idle.clone().unwrap_or(AnimationDef {
+                frames: (archetype.idle_frame, archetype.idle_frame),
+                frame_duration: 1.,
+                mode: AnimationMode::Repeat,
+            });
+            let states = [
+                (CharacterState::Idle, Some(&idle), AnimationMode::Repeat),
+                (
+                    CharacterState::Walk,
+                    Some(&archetype.walk),
+                    AnimationMode::Repeat,
+                ),
+                (
+                    CharacterState::Hurt,
+                    archetype.hurt.as_ref(),
+                    AnimationMode::Repeat,
+                ),
+                (
+                    CharacterState::Attack,
+                    archetype.attack.as_ref(),
+                    AnimationMode::Once,
+                ),
+                (
+                    CharacterState::Death,
+                    Some(&archetype.death),
+                    AnimationMode::Once,
+                ),
+            ];
+            for (state, def, mode) in states {
+                if let Some(def) = def {
+                    let def = AnimationDef {
+                        mode,
+                        ..def.clone()
+                    };
+                    library.set(
+                        archetype_animation(name, state.name()),
+                        def.to_animation(),
+                        &mut animations,
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn animate_characters(
+    mut commands: Commands,
+    time: Res<SimTime>,
+    library: Res<AnimationLibrary>,
+    mut characters: Query<(
+        Entity,
+        &mut CharacterAnimator,
+        Option<&InvincibilityFrames>,
+        Option<&Health>,
+        Option<&Dead>,
+    )>,
+) {
+    for (e, mut animator, invincibility, health, dead) in characters.iter_mut() {
+        animator.hurt = invincibility.is_some();
+        animator.dead = dead.is_some() || health.map_or(false, |health| health.0 <= 0.);
+        animator.attacking = (animator.attacking - time.delta_seconds()).max(0.);
+
+        let next = {
+            let animator: &CharacterAnimator = &animator;
+            animator.next_state(|state| library.contains(&animator.animation(state)))
+        };
+        if animator.state == Some(next) {
+            continue;
+        }
+        animator.state = Some(next);
+
+        let animation = animator.animation(next);
+        if library.contains(&animation) {
+            commands
+                .entity(e)
+                .insert(library.get(&animation))
+                .insert(Play);
+        } else {
+            // only death has no fallback, the character stops on its current frame
+            commands.entity(e).remove::<Play>();
+        }
+    }
+}
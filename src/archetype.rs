@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use crate::animation::AnimationDef;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+pub struct ArchetypePlugin;
+
+/// All enemy archetypes, keyed by name
+/// Loaded from `*.enemies.ron` files, see `assets/data/archetypes.enemies.ron`
+#[derive(Deserialize, TypeUuid)]
+#[serde(transparent)]
+#[uuid = "6a3a1c6e-51c4-4bb0-9a43-2f1c3f0b7e21"]
+pub struct EnemyArchetypes(pub HashMap<String, EnemyArchetype>);
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct EnemyArchetype {
+    pub atlas: AtlasDef,
+    pub idle_frame: usize,
+    /// Defaults to `idle_frame` alone
+    #[serde(default)]
+    pub idle: Option<AnimationDef>,
+    pub walk: AnimationDef,
+    #[serde(default)]
+    pub hurt: Option<AnimationDef>,
+    /// Played when a ranged enemy shoots
+    #[serde(default)]
+    pub attack: Option<AnimationDef>,
+    pub death: AnimationDef,
+    pub corpse_frame: usize,
+    pub speed: f32,
+    pub health: f32,
+    pub damage: f32,
+    pub xp: u32,
+    /// Width and height of the hit and hurt boxes, defaults to the atlas tile size
+    #[serde(default)]
+    pub hitbox: Option<(f32, f32)>,
+    pub behaviour: EnemyBehaviour,
+    /// Shows a health bar above the enemy, meant for elites
+    #[serde(default)]
+    pub health_bar: bool,
+}
+
+impl EnemyArchetype {
+    pub fn hitbox_size(&self) -> Vec2 {
+        let (w, h) = self.hitbox.unwrap_or(self.atlas.tile_size);
+        Vec2::new(w, h)
+    }
+}
+
+/// A sprite sheet cut in a grid of tiles
+#[derive(Deserialize, Clone, Debug)]
+pub struct AtlasDef {
+    pub path: String,
+    pub tile_size: (f32, f32),
+    pub columns: usize,
+    pub rows: usize,
+}
+
+#[derive(Component, Deserialize, Clone, Copy, Debug)]
+pub enum EnemyBehaviour {
+    /// Walks straight to the player
+    Chase,
+    /// Keeps its distance and shoots projectiles from the magic atlas at the player
+    Ranged {
+        range: f32,
+        cooldown: f32,
+        projectile_speed: f32,
+        projectile_frame: usize,
+    },
+}
+
+/// Texture atlases built from `AtlasDef`s, keyed by image path
+#[derive(Default)]
+pub struct ArchetypeAtlases(HashMap<String, Handle<TextureAtlas>>);
+
+impl ArchetypeAtlases {
+    pub fn get_or_load(
+        &mut self,
+        def: &AtlasDef,
+        asset_server: &AssetServer,
+        texture_atlases: &mut Assets<TextureAtlas>,
+    ) -> Handle<TextureAtlas> {
+        self.0
+            .entry(def.path.clone())
+            .or_insert_with(|| {
+                let texture = asset_server.load(def.path.as_str());
+                let (w, h) = def.tile_size;
+                texture_atlases.add(TextureAtlas::from_grid(
+                    texture,
+                    Vec2::new(w, h),
+                    def.columns,
+                    def.rows,
+                ))
+            })
+            .clone()
+    }
+}
+
+#[derive(Default)]
+pub struct EnemyArchetypesLoader;
+
+impl AssetLoader for EnemyArchetypesLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let archetypes: EnemyArchetypes = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(archetypes));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["enemies.ron"]
+    }
+}
+
+/// This plugin registers the enemy archetype asset, loaded by the `LoadingPlugin`
+/// With the `dev` feature, changes to the files are picked up by newly spawned enemies
+impl Plugin for ArchetypePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<EnemyArchetypes>()
+            .init_asset_loader::<EnemyArchetypesLoader>()
+            .init_resource::<ArchetypeAtlases>();
+    }
+}
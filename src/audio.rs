@@ -1,36 +1,139 @@
-use crate::actions::Actions;
-use crate::loading::AudioAssets;
-use crate::GameState;
+use std::collections::HashMap;
+
+use crate::music::{MusicDirector, MusicLibrary, MusicTrack};
+use crate::settings::Settings;
+use crate::sfx::{PlaySound, SfxMap};
+use bevy::asset::LoadState;
 use bevy::prelude::*;
-use bevy_kira_audio::{Audio, AudioPlugin};
+use bevy_kira_audio::{Audio, AudioChannel, AudioPlugin, AudioSource};
 
 pub struct InternalAudioPlugin;
 
+/// The two music layers are crossfaded by their volume, the sound effects are mixed apart
+struct AudioChannels {
+    music_calm: AudioChannel,
+    music_intense: AudioChannel,
+    sfx: AudioChannel,
+}
+
+impl Default for AudioChannels {
+    fn default() -> Self {
+        AudioChannels {
+            music_calm: AudioChannel::new("music_calm".to_string()),
+            music_intense: AudioChannel::new("music_intense".to_string()),
+            sfx: AudioChannel::new("sfx".to_string()),
+        }
+    }
+}
+
+/// Music and sound effects, loaded on startup
+#[derive(Default)]
+struct AudioHandles(HashMap<String, Handle<AudioSource>>);
+
+/// Track of the `MusicDirector` the music channels play
+#[derive(Default)]
+struct MusicPlayback {
+    track: Option<MusicTrack>,
+    /// The layers start together once both are loaded, so that they stay in sync
+    started: bool,
+}
+
 // This plugin is responsible to control the game audio
+// The music follows the `MusicDirector` and the sound effects are picked by the `SfxPlugin`,
+// this plugin plays them once loaded
 impl Plugin for InternalAudioPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(AudioPlugin)
-            .add_system_set(
-                SystemSet::on_enter(GameState::Playing).with_system(start_audio.system()),
-            )
-            .add_system_set(
-                SystemSet::on_update(GameState::Playing).with_system(control_flying_sound.system()),
-            );
+            .init_resource::<AudioChannels>()
+            .init_resource::<AudioHandles>()
+            .add_startup_system(load_audio)
+            .add_system(play_music)
+            .add_system(play_sfx)
+            .add_system(apply_volume);
     }
 }
 
-fn start_audio(audio_assets: Res<AudioAssets>, audio: Res<Audio>) {
-    audio.set_volume(0.3);
-    audio.play_looped(audio_assets.flying.clone());
-    audio.pause();
+fn load_audio(
+    asset_server: Res<AssetServer>,
+    music: Res<MusicLibrary>,
+    sfx: Res<SfxMap>,
+    mut handles: ResMut<AudioHandles>,
+) {
+    for path in music.paths().chain(sfx.paths()) {
+        handles.0.insert(path.to_string(), asset_server.load(path));
+    }
+}
+
+fn apply_volume(audio: Res<Audio>, channels: Res<AudioChannels>, settings: Res<Settings>) {
+    if settings.is_changed() {
+        audio.set_volume_in_channel(settings.sfx_volume(), &channels.sfx);
+    }
+}
+
+fn play_music(
+    audio: Res<Audio>,
+    channels: Res<AudioChannels>,
+    asset_server: Res<AssetServer>,
+    handles: Res<AudioHandles>,
+    settings: Res<Settings>,
+    director: Res<MusicDirector>,
+    mut playback: Local<MusicPlayback>,
+) {
+    if director.track() != playback.track.as_ref() {
+        audio.stop_channel(&channels.music_calm);
+        audio.stop_channel(&channels.music_intense);
+        *playback = MusicPlayback {
+            track: director.track().cloned(),
+            started: false,
+        };
+    }
+
+    let load_state = |path: &String| {
+        handles.0.get(path).map_or(LoadState::Failed, |handle| {
+            asset_server.get_load_state(handle)
+        })
+    };
+    if let Some(track) = playback.track.as_ref().filter(|_| !playback.started) {
+        let layers = [
+            (Some(&track.calm), &channels.music_calm),
+            (track.intense.as_ref(), &channels.music_intense),
+        ];
+        // a missing layer is skipped, the asset server already reported it
+        let ready = layers.iter().all(|(path, _)| {
+            path.map_or(true, |path| {
+                matches!(load_state(path), LoadState::Loaded | LoadState::Failed)
+            })
+        });
+        if ready {
+            for (path, channel) in layers {
+                if let Some(path) = path.filter(|path| load_state(*path) == LoadState::Loaded) {
+                    audio.play_looped_in_channel(handles.0[path].clone(), channel);
+                }
+            }
+            playback.started = true;
+        }
+    }
+
+    let (calm, intense) = director.layer_volumes();
+    audio.set_volume_in_channel(calm * settings.music_volume(), &channels.music_calm);
+    audio.set_volume_in_channel(intense * settings.music_volume(), &channels.music_intense);
 }
 
-fn control_flying_sound(_actions: Res<Actions>, _audio: Res<Audio>) {
-    /*
-    if actions.player_movement.is_some() {
-        audio.resume();
-    } else {
-        audio.pause()
+fn play_sfx(
+    audio: Res<Audio>,
+    channels: Res<AudioChannels>,
+    asset_server: Res<AssetServer>,
+    handles: Res<AudioHandles>,
+    mut event_sound: EventReader<PlaySound>,
+) {
+    for sound in event_sound.iter() {
+        let handle = match handles.0.get(&sound.path) {
+            Some(handle) => handle,
+            None => continue,
+        };
+        // a missing file is only reported once, by the asset server
+        if asset_server.get_load_state(handle) == LoadState::Loaded {
+            audio.play_in_channel(handle.clone(), &channels.sfx);
+        }
     }
-    */
 }
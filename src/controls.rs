@@ -0,0 +1,275 @@
+use crate::input_map::{Action, ActiveGamepad, AimMode, Binding, InputMap, Inputs};
+use crate::loading::FontAssets;
+use crate::menu::ButtonColors;
+use crate::utils::despawn_with;
+use crate::GameState;
+use bevy::prelude::*;
+
+pub struct ControlsPlugin;
+
+/// Action waiting for a key or button press to be bound
+#[derive(Default)]
+struct Rebinding(Option<Action>);
+
+#[derive(Component)]
+struct ControlsUi;
+
+#[derive(Component, Clone, Copy)]
+enum ControlsButton {
+    Bind(Action),
+    Clear(Action),
+    AimMode,
+    Reset,
+    Back,
+}
+
+/// This plugin draws the rebinding screen during `GameState::Controls`,
+/// pushed over the settings menu
+/// Every action can be bound to any number of keys, mouse and gamepad buttons,
+/// the input map is saved when leaving the screen
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Rebinding>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Controls)
+                    .with_system(listen_for_binding.chain(click_controls_button))
+                    .with_system(rebuild_controls_ui),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Controls)
+                    .with_system(despawn_with::<ControlsUi>)
+                    .with_system(save_input_map),
+            );
+    }
+}
+
+fn rebuild_controls_ui(
+    mut commands: Commands,
+    input_map: Res<InputMap>,
+    rebinding: Res<Rebinding>,
+    font_assets: Res<FontAssets>,
+    button_colors: Res<ButtonColors>,
+    ui: Query<Entity, With<ControlsUi>>,
+) {
+    // also true on the first frame of the screen, the resources were just added or changed
+    if !input_map.is_changed() && !rebinding.is_changed() && ui.iter().next().is_some() {
+        return;
+    }
+    for e in ui.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+
+    let text_style = |font_size| TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(ControlsUi)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(10.)),
+                    ..Default::default()
+                },
+                text: Text::with_section("Controls", text_style(40.), Default::default()),
+                ..Default::default()
+            });
+
+            for action in Action::ALL {
+                let bindings = if rebinding.0 == Some(action) {
+                    "Press a key or button...".to_string()
+                } else {
+                    input_map
+                        .bindings(action)
+                        .iter()
+                        .map(Binding::name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        color: Color::NONE.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        for (text, width) in [(action.name(), 120.), (bindings.as_str(), 260.)] {
+                            parent.spawn_bundle(TextBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(width), Val::Auto),
+                                    ..Default::default()
+                                },
+                                text: Text::with_section(text, text_style(18.), Default::default()),
+                                ..Default::default()
+                            });
+                        }
+                        for (button, label) in [
+                            (ControlsButton::Bind(action), "Add"),
+                            (ControlsButton::Clear(action), "Clear"),
+                        ] {
+                            spawn_button(
+                                parent,
+                                button,
+                                label,
+                                60.,
+                                text_style(18.),
+                                &button_colors,
+                            );
+                        }
+                    });
+            }
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(10.)),
+                        ..Default::default()
+                    },
+                    color: Color::NONE.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    let aim = format!("Aim: {}", input_map.aim.name());
+                    for (button, label, width) in [
+                        (ControlsButton::AimMode, aim.as_str(), 300.),
+                        (ControlsButton::Reset, "Defaults", 120.),
+                        (ControlsButton::Back, "Back", 120.),
+                    ] {
+                        spawn_button(
+                            parent,
+                            button,
+                            label,
+                            width,
+                            text_style(24.),
+                            &button_colors,
+                        );
+                    }
+                });
+        });
+}
+
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    button: ControlsButton,
+    label: &str,
+    width: f32,
+    text_style: TextStyle,
+    button_colors: &ButtonColors,
+) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(width), Val::Px(text_style.font_size + 8.)),
+                margin: Rect::all(Val::Px(2.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: button_colors.normal,
+            ..Default::default()
+        })
+        .insert(button)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(label, text_style, Default::default()),
+                ..Default::default()
+            });
+        });
+}
+
+/// Binds the first key or button pressed while an action is waiting for one
+/// otherwise `Action::Cancel` leaves the screen
+/// Returns whether the screen was busy rebinding, so that the click isn't also handled as a button
+fn listen_for_binding(
+    mut rebinding: ResMut<Rebinding>,
+    mut input_map: ResMut<InputMap>,
+    mut state: ResMut<State<GameState>>,
+    active: Res<ActiveGamepad>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+) -> bool {
+    let inputs = Inputs {
+        keys: &keyboard_input,
+        mouse: &mouse_input,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad: active.gamepad,
+    };
+    let action = match rebinding.0 {
+        Some(action) => action,
+        None => {
+            if input_map.just_pressed(Action::Cancel, inputs) {
+                leave_controls(&mut state);
+            }
+            return false;
+        }
+    };
+    if let Some(binding) = Binding::any_just_pressed(inputs) {
+        input_map.bind(action, binding);
+        rebinding.0 = None;
+    }
+    true
+}
+
+type ControlsButtonInteraction<'a> = (&'a ControlsButton, &'a Interaction, &'a mut UiColor);
+
+fn click_controls_button(
+    In(rebinding_busy): In<bool>,
+    button_colors: Res<ButtonColors>,
+    mut rebinding: ResMut<Rebinding>,
+    mut input_map: ResMut<InputMap>,
+    mut state: ResMut<State<GameState>>,
+    mut interaction_query: Query<ControlsButtonInteraction, Changed<Interaction>>,
+) {
+    for (button, interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked if !rebinding_busy => match *button {
+                ControlsButton::Bind(action) => rebinding.0 = Some(action),
+                ControlsButton::Clear(action) => input_map.clear(action),
+                ControlsButton::AimMode => {
+                    input_map.aim = match input_map.aim {
+                        AimMode::Auto => AimMode::Manual,
+                        AimMode::Manual => AimMode::Auto,
+                    }
+                }
+                ControlsButton::Reset => *input_map = InputMap::default(),
+                ControlsButton::Back => leave_controls(&mut state),
+            },
+            Interaction::Clicked => {}
+            Interaction::Hovered => {
+                *color = button_colors.hovered;
+            }
+            Interaction::None => {
+                *color = button_colors.normal;
+            }
+        }
+    }
+}
+
+/// Back to the settings menu the screen was pushed over, or else to the main menu
+fn leave_controls(state: &mut State<GameState>) {
+    if state.pop().is_err() {
+        let _ = state.set(GameState::Menu);
+    }
+}
+
+fn save_input_map(input_map: Res<InputMap>, mut rebinding: ResMut<Rebinding>) {
+    rebinding.0 = None;
+    input_map.save();
+}
@@ -1,9 +1,17 @@
-use std::time::Duration;
-
+use crate::animation::CharacterAnimator;
+use crate::archetype::{EnemyArchetype, EnemyBehaviour};
+use crate::health_display::HealthDisplay;
 use crate::loading::TextureAssets;
-use crate::player::{BaseMoveSpeed, Health, HitBox, HurtBox, Player};
+use crate::player::{BaseMoveSpeed, DamageKind, Health, HitBox, HurtBox, MaxHealth, Player};
+use crate::pool::{Pool, PoolPlugin, Poolable};
+use crate::projectile::{Projectile, ProjectileTarget};
+use crate::timestep::{FixedTimestepAppExt, Interpolated, SimTime};
+use crate::utils::despawn_with;
+use crate::waves::Boss;
 use crate::{GameConfiguration, GameState};
 use benimator::{Play, SpriteSheetAnimation};
+use bevy::diagnostic::DiagnosticId;
+use bevy::ecs::world::EntityMut;
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
 
@@ -23,116 +31,156 @@ pub struct Alive;
 #[derive(Component)]
 pub struct Enemy;
 
+/// Corpses go back to the pool, the next enemies reuse them
+impl Poolable for Enemy {
+    const NAME: &'static str = "enemy";
+    const FREE_DIAGNOSTIC: DiagnosticId =
+        DiagnosticId::from_u128(0x6b1d5e2a_93f4_4c8e_a1b7_2f0c9d3e8a41);
+    const ACTIVE_DIAGNOSTIC: DiagnosticId =
+        DiagnosticId::from_u128(0x6b1d5e2a_93f4_4c8e_a1b7_2f0c9d3e8a42);
+
+    fn reset(entity: &mut EntityMut) {
+        entity
+            .remove_bundle::<(
+                Enemy,
+                CharacterAnimator,
+                Interpolated,
+                Archetype,
+                Alive,
+                Dead,
+                Corpse,
+                BaseMoveSpeed,
+                HurtBox,
+                Health,
+                MaxHealth,
+                HitBox,
+            )>()
+            .remove_bundle::<(
+                EnemyBehaviour,
+                RangedAttack,
+                HealthDisplay,
+                Boss,
+                Handle<SpriteSheetAnimation>,
+                Play,
+            )>();
+    }
+}
+
+/// Name of the `EnemyArchetype` the enemy was spawned from
 #[derive(Component)]
-pub struct EnemySpawner {
+pub struct Archetype(pub String);
+
+#[derive(Component)]
+pub struct RangedAttack {
     timer: Timer,
-    times: u32,
-    bag: u32,
-    spread: f32,
 }
 
+/// Seconds the attack animation plays after a ranged enemy shoots
+const ATTACK_ANIMATION_TIME: f32 = 0.4;
+
 /// This plugin handles player related stuff like movement
 /// Player logic is only active during the State `GameState::Playing`
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(enemy_setup))
-            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(spawner_tick))
-            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(move_enemy));
+        app.add_plugin(PoolPlugin::<Enemy>::default())
+            .add_fixed_system_set(
+                SystemSet::new()
+                    .with_system(move_enemy)
+                    .with_system(enemy_ranged_attack),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(despawn_with::<Enemy>),
+            );
     }
 }
 
-fn spawner_tick(
-    mut commands: Commands,
-    textures: Res<TextureAssets>,
-    assets: Res<Assets<TextureAtlas>>,
-    mut spawner: Query<&mut EnemySpawner>,
-    time: Res<Time>,
-    config: Res<GameConfiguration>,
-    mut animations: ResMut<Assets<SpriteSheetAnimation>>,
-) {
-    let mut rng = rand::thread_rng();
-    use rand::prelude::*;
-
-    let animation_handle = animations.add(
-        SpriteSheetAnimation::from_range(
-            28..=31,                            // Indices of the sprite atlas
-            Duration::from_secs_f64(1.0 / 4.0), // Duration of each frame
-        )
-        .repeat(),
-    );
-
-    for mut spawner in spawner.iter_mut() {
-        spawner.timer.tick(time.delta());
-
-        if spawner.timer.just_finished() && spawner.times > 0 {
-            spawner.times -= 1;
-
-            let t = rng.gen_range(0f32..(std::f32::consts::PI * 2.));
-            let r = 300.;
-            for _ in 0..spawner.bag {
-                let dx = rng.gen_range((-spawner.spread / 2.)..(spawner.spread / 2.));
-                let dy = rng.gen_range((-spawner.spread / 2.)..(spawner.spread / 2.));
-                let x = r * t.sin() + dx;
-                let y = r * t.cos() + dy;
-
-                let texture = assets.get(&textures.necromancer).unwrap();
-                let size = texture.textures[0].size();
-
-                commands
-                    .spawn_bundle(SpriteSheetBundle {
-                        texture_atlas: textures.necromancer.clone(),
-                        transform: Transform::from_translation(Vec3::new(
-                            x,
-                            y,
-                            sprite_z(Vec2::new(x, y)),
-                        ))
-                        .with_scale(Vec3::splat(config.scale)),
-                        sprite: TextureAtlasSprite::new(27),
-
-                        ..Default::default()
-                    })
-                    .insert(animation_handle.clone())
-                    .insert(Play)
-                    .insert(Enemy)
-                    .insert(Alive)
-                    .insert(BaseMoveSpeed(10.))
-                    .insert(HurtBox {
-                        pos: Vec2::ZERO,
-                        size,
-                    })
-                    .insert(Health(3.))
-                    .insert(HitBox {
-                        pos: Vec2::ZERO,
-                        size,
-                        damage: 1.,
-                    });
-            }
-        }
-    }
-}
+/// Spawns an enemy of the archetype `name` at `p`, animated by a `CharacterAnimator`
+/// Its health is scaled by `health_multiplier`
+/// The entity is taken from the `Pool<Enemy>` when a corpse is waiting there
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    pool: &mut Pool<Enemy>,
+    name: &str,
+    archetype: &EnemyArchetype,
+    atlas: Handle<TextureAtlas>,
+    p: Vec2,
+    scale: f32,
+    health_multiplier: f32,
+) -> Entity {
+    let size = archetype.hitbox_size();
+    let health = archetype.health * health_multiplier;
 
-fn enemy_setup(mut commands: Commands) {
-    commands.spawn().insert(EnemySpawner {
-        timer: Timer::new(Duration::from_secs_f32(2.0), true),
-        times: 100,
-        bag: 5,
-        spread: 50.,
+    let mut enemy = pool.spawn(commands);
+    enemy.insert_bundle(SpriteSheetBundle {
+        texture_atlas: atlas,
+        transform: Transform::from_translation(p.extend(sprite_z(p)))
+            .with_scale(Vec3::splat(scale)),
+        sprite: TextureAtlasSprite::new(archetype.idle_frame),
+
+        ..Default::default()
     });
+    enemy
+        .insert(CharacterAnimator::new(name))
+        .insert(Enemy)
+        .insert(Interpolated::default())
+        .insert(Archetype(name.to_string()))
+        .insert(Alive)
+        .insert(BaseMoveSpeed(archetype.speed))
+        .insert(HurtBox {
+            pos: Vec2::ZERO,
+            size,
+        })
+        .insert(Health(health))
+        .insert(MaxHealth(health))
+        .insert(HitBox {
+            pos: Vec2::ZERO,
+            size,
+            damage: archetype.damage,
+        })
+        .insert(archetype.behaviour);
+
+    if let EnemyBehaviour::Ranged { cooldown, .. } = archetype.behaviour {
+        enemy.insert(RangedAttack {
+            timer: Timer::from_seconds(cooldown, true),
+        });
+    }
+    if archetype.health_bar {
+        enemy.insert(HealthDisplay::default());
+    }
+
+    enemy.id()
 }
 
+#[allow(clippy::type_complexity)]
 fn move_enemy(
-    time: Res<Time>,
+    time: Res<SimTime>,
     mut enemy_query: Query<
-        (&mut Transform, &BaseMoveSpeed, &mut TextureAtlasSprite),
+        (
+            &mut Transform,
+            &BaseMoveSpeed,
+            &mut TextureAtlasSprite,
+            &mut CharacterAnimator,
+            &EnemyBehaviour,
+        ),
         (With<Enemy>, With<Alive>, Without<Player>),
     >,
     player_query: Query<&Transform, (With<Player>, Without<Enemy>)>,
 ) {
     let player = player_query.single();
 
-    for (mut enemy_transform, base_speed, mut enemy_sprite) in enemy_query.iter_mut() {
-        let delta = (player.translation - enemy_transform.translation).clamp_length_max(1.);
+    for (mut enemy_transform, base_speed, mut enemy_sprite, mut animator, behaviour) in
+        enemy_query.iter_mut()
+    {
+        let mut delta = (player.translation - enemy_transform.translation).clamp_length_max(1.);
+        if let EnemyBehaviour::Ranged { range, .. } = behaviour {
+            // ranged enemies stop once the player is in range, but keep facing them
+            if (player.translation - enemy_transform.translation).length() <= *range {
+                delta = Vec3::ZERO;
+            }
+        }
         let movement = delta * base_speed.0 * time.delta_seconds();
+        animator.moving = movement != Vec3::ZERO;
 
         if movement.x <= 0. {
             enemy_sprite.flip_x = true;
@@ -147,6 +195,71 @@ fn move_enemy(
     }
 }
 
+#[allow(clippy::type_complexity)]
+fn enemy_ranged_attack(
+    mut commands: Commands,
+    time: Res<SimTime>,
+    textures: Res<TextureAssets>,
+    config: Res<GameConfiguration>,
+    mut enemies: Query<
+        (
+            Entity,
+            &Transform,
+            &HitBox,
+            &EnemyBehaviour,
+            &mut RangedAttack,
+            &mut CharacterAnimator,
+        ),
+        (With<Enemy>, With<Alive>),
+    >,
+    player: Query<&Transform, With<Player>>,
+) {
+    let player = player.single().translation.xy();
+
+    for (e, transform, hit_box, behaviour, mut attack, mut animator) in enemies.iter_mut() {
+        let (range, projectile_speed, projectile_frame) = match *behaviour {
+            EnemyBehaviour::Ranged {
+                range,
+                projectile_speed,
+                projectile_frame,
+                ..
+            } => (range, projectile_speed, projectile_frame),
+            EnemyBehaviour::Chase => continue,
+        };
+        attack.timer.tick(time.delta());
+
+        let p = transform.translation.xy();
+        let to_player = player - p;
+        if !attack.timer.just_finished() || to_player.length() > range || to_player == Vec2::ZERO {
+            continue;
+        }
+        animator.attacking = ATTACK_ANIMATION_TIME;
+
+        commands
+            .spawn_bundle(SpriteSheetBundle {
+                texture_atlas: textures.magic.clone(),
+                transform: Transform::from_translation(p.extend(sprite_z(p) + 0.1))
+                    .with_scale(Vec3::splat(config.scale / 2.)),
+                sprite: TextureAtlasSprite::new(projectile_frame),
+                ..Default::default()
+            })
+            .insert(HitBox {
+                pos: Vec2::ZERO,
+                size: Vec2::splat(8. * config.scale),
+                damage: hit_box.damage,
+            })
+            .insert(Interpolated::default())
+            .insert(Projectile::new(
+                e,
+                to_player.normalize() * projectile_speed,
+                range * 2. / projectile_speed,
+                0,
+                DamageKind::Magic,
+                ProjectileTarget::Player,
+            ));
+    }
+}
+
 pub fn sprite_z(xy: Vec2) -> f32 {
     let base_z = 1.;
 
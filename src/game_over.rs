@@ -0,0 +1,216 @@
+use crate::enemy::Enemy;
+use crate::input_map::{Action, ActiveGamepad, InputMap, Inputs};
+use crate::loading::FontAssets;
+use crate::menu::ButtonColors;
+use crate::player::{ApplyDamage, DieEvent, Health, Player};
+use crate::progression::Level;
+use crate::rng::GameRng;
+use crate::timestep::{FixedTimestepAppExt, SimTime};
+use crate::utils::despawn_with;
+use crate::GameState;
+use bevy::prelude::*;
+
+pub struct GameOverPlugin;
+
+/// Results of the current run, shown on the game over screen
+#[derive(Default)]
+pub struct RunStats {
+    pub time: f32,
+    pub kills: u32,
+    pub level: u32,
+}
+
+#[derive(Component)]
+struct GameOverUi;
+
+#[derive(Component, Clone, Copy)]
+enum GameOverButton {
+    Retry,
+    Menu,
+}
+
+/// This plugin tracks the run results and ends the run when the player dies
+/// The run's entities are despawned by each plugin when leaving `GameState::Playing`
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunStats>()
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(reset_run_stats))
+            .add_fixed_system_set(
+                SystemSet::new()
+                    .with_system(track_run_time)
+                    .with_system(count_kills)
+                    .with_system(end_run.after(ApplyDamage)),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(setup_game_over))
+            .add_system_set(
+                SystemSet::on_update(GameState::GameOver)
+                    .with_system(click_game_over_button)
+                    .with_system(game_over_shortcuts),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::GameOver).with_system(despawn_with::<GameOverUi>),
+            );
+    }
+}
+
+fn reset_run_stats(mut stats: ResMut<RunStats>) {
+    *stats = RunStats::default();
+}
+
+fn track_run_time(time: Res<SimTime>, mut stats: ResMut<RunStats>) {
+    stats.time += time.delta_seconds();
+}
+
+fn count_kills(
+    mut event_die: EventReader<DieEvent>,
+    enemies: Query<(), With<Enemy>>,
+    mut stats: ResMut<RunStats>,
+) {
+    for event in event_die.iter() {
+        if enemies.get(event.entity).is_ok() {
+            stats.kills += 1;
+        }
+    }
+}
+
+/// Checks the health of the player every tick, so that a run can't outlive its player
+fn end_run(
+    player: Query<(&Health, &Level), With<Player>>,
+    mut stats: ResMut<RunStats>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let (health, level) = match player.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    if health.0 > 0. {
+        return;
+    }
+    stats.level = level.0;
+    // wins over a level-up or a pause queued during the same frame
+    let _ = state.overwrite_replace(GameState::GameOver);
+}
+
+fn setup_game_over(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    button_colors: Res<ButtonColors>,
+    stats: Res<RunStats>,
+    rng: Res<GameRng>,
+) {
+    let text_style = |font_size| TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    let minutes = stats.time as u32 / 60;
+    let seconds = stats.time as u32 % 60;
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(GameOverUi)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(10.)),
+                    ..Default::default()
+                },
+                text: Text::with_section("Game Over", text_style(40.), Default::default()),
+                ..Default::default()
+            });
+            for line in [
+                format!("Survived {}:{:02}", minutes, seconds),
+                format!("Level {}", stats.level),
+                format!("{} enemies defeated", stats.kills),
+                format!("Seed {}", rng.seed()),
+            ] {
+                parent.spawn_bundle(TextBundle {
+                    text: Text::with_section(line, text_style(24.), Default::default()),
+                    ..Default::default()
+                });
+            }
+
+            for (button, label) in [
+                (GameOverButton::Retry, "Retry"),
+                (GameOverButton::Menu, "Menu"),
+            ] {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(120.0), Val::Px(50.0)),
+                            margin: Rect::all(Val::Px(10.)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        color: button_colors.normal,
+                        ..Default::default()
+                    })
+                    .insert(button)
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(label, text_style(32.), Default::default()),
+                            ..Default::default()
+                        });
+                    });
+            }
+        });
+}
+
+/// `Action::Confirm` retries, `Action::Cancel` goes back to the menu
+fn game_over_shortcuts(
+    input_map: Res<InputMap>,
+    active: Res<ActiveGamepad>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let inputs = Inputs {
+        keys: &keyboard_input,
+        mouse: &mouse_input,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad: active.gamepad,
+    };
+    if input_map.just_pressed(Action::Confirm, inputs) {
+        let _ = state.set(GameState::Playing);
+    } else if input_map.just_pressed(Action::Cancel, inputs) {
+        let _ = state.set(GameState::Menu);
+    }
+}
+
+type GameOverButtonInteraction<'a> = (&'a GameOverButton, &'a Interaction, &'a mut UiColor);
+
+fn click_game_over_button(
+    button_colors: Res<ButtonColors>,
+    mut state: ResMut<State<GameState>>,
+    mut interaction_query: Query<GameOverButtonInteraction, Changed<Interaction>>,
+) {
+    for (button, interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                let next = match button {
+                    GameOverButton::Retry => GameState::Playing,
+                    GameOverButton::Menu => GameState::Menu,
+                };
+                let _ = state.set(next);
+            }
+            Interaction::Hovered => {
+                *color = button_colors.hovered;
+            }
+            Interaction::None => {
+                *color = button_colors.normal;
+            }
+        }
+    }
+}
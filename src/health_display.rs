@@ -0,0 +1,234 @@
+use crate::loading::FontAssets;
+use crate::player::{DealDamageEvent, Health, MaxHealth};
+use crate::settings::Settings;
+use crate::timestep::{FixedTimestepAppExt, SimTime};
+use crate::utils::despawn_with;
+use crate::GameState;
+use bevy::prelude::*;
+
+pub struct HealthDisplayPlugin;
+
+/// Opt-in health display for any entity with `Health`
+/// The bar needs a `MaxHealth` to show a ratio, the number is shown either way
+#[derive(Component)]
+pub struct HealthDisplay {
+    pub bar: bool,
+    pub number: bool,
+    /// Position relative to the entity, in the entity's local space
+    pub offset: Vec2,
+}
+
+impl Default for HealthDisplay {
+    fn default() -> Self {
+        HealthDisplay {
+            bar: true,
+            number: false,
+            offset: Vec2::new(0., -10.),
+        }
+    }
+}
+
+const BAR_SIZE: Vec2 = Vec2::new(16., 2.);
+
+#[derive(Component)]
+struct HealthBarFill;
+
+#[derive(Component)]
+struct HealthText;
+
+/// Amount of damage floating above the entity that took it, fading away
+#[derive(Component)]
+struct DamageNumber {
+    timer: Timer,
+}
+
+const DAMAGE_NUMBER_DURATION: f32 = 0.6;
+/// Pixels per second
+const DAMAGE_NUMBER_SPEED: f32 = 30.;
+
+/// This plugin spawns the health bars and numbers and keeps them in sync with `Health`
+/// Damage numbers are shown when enabled in the `Settings`
+impl Plugin for HealthDisplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(spawn_health_display)
+                .with_system(update_health_display),
+        )
+        .add_fixed_system_set(
+            SystemSet::new()
+                .with_system(spawn_damage_numbers)
+                .with_system(float_damage_numbers),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Playing).with_system(despawn_with::<DamageNumber>),
+        );
+    }
+}
+
+fn health_ratio(health: &Health, max_health: Option<&MaxHealth>) -> f32 {
+    match max_health {
+        Some(max_health) if max_health.0 > 0. => (health.0 / max_health.0).clamp(0., 1.),
+        _ => 1.,
+    }
+}
+
+fn bar_fill_transform(ratio: f32, offset: Vec2) -> Transform {
+    Transform::from_translation(Vec3::new(
+        offset.x - BAR_SIZE.x * (1. - ratio) / 2.,
+        offset.y,
+        0.2,
+    ))
+    .with_scale(Vec3::new(ratio, 1., 1.))
+}
+
+fn health_text(health: &Health) -> String {
+    format!("{}", health.0.max(0.).ceil())
+}
+
+fn spawn_health_display(
+    mut commands: Commands,
+    fonts: Res<FontAssets>,
+    q: Query<
+        (
+            Entity,
+            &HealthDisplay,
+            &Health,
+            Option<&MaxHealth>,
+            &Transform,
+        ),
+        Added<HealthDisplay>,
+    >,
+) {
+    for (e, display, health, max_health, transform) in q.iter() {
+        let ratio = health_ratio(health, max_health);
+
+        commands.entity(e).with_children(|parent| {
+            if display.bar {
+                parent.spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgb(0.2, 0.2, 0.2),
+                        custom_size: Some(BAR_SIZE),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_translation(display.offset.extend(0.1)),
+                    ..Default::default()
+                });
+                parent
+                    .spawn_bundle(SpriteBundle {
+                        sprite: Sprite {
+                            color: Color::rgb(0.8, 0.1, 0.1),
+                            custom_size: Some(BAR_SIZE),
+                            ..Default::default()
+                        },
+                        transform: bar_fill_transform(ratio, display.offset),
+                        ..Default::default()
+                    })
+                    .insert(HealthBarFill);
+            }
+
+            if display.number {
+                let style = TextStyle {
+                    font: fonts.fira_sans.clone(),
+                    font_size: 10.,
+                    color: Color::WHITE,
+                };
+                let alignment = TextAlignment {
+                    vertical: VerticalAlign::Center,
+                    horizontal: HorizontalAlign::Center,
+                };
+                // the text size is given in pixels, undo the scale of the parent sprite
+                let y = if display.bar { -4. } else { 0. };
+                parent
+                    .spawn_bundle(Text2dBundle {
+                        text: Text::with_section(health_text(health), style, alignment),
+                        transform: Transform::from_translation(Vec3::new(
+                            display.offset.x,
+                            display.offset.y + y,
+                            0.3,
+                        ))
+                        .with_scale(transform.scale.recip()),
+                        ..Default::default()
+                    })
+                    .insert(HealthText);
+            }
+        });
+    }
+}
+
+fn update_health_display(
+    q: Query<
+        (&HealthDisplay, &Health, Option<&MaxHealth>, &Children),
+        Or<(Changed<Health>, Changed<MaxHealth>)>,
+    >,
+    mut bars: Query<&mut Transform, With<HealthBarFill>>,
+    mut texts: Query<&mut Text, With<HealthText>>,
+) {
+    for (display, health, max_health, children) in q.iter() {
+        let ratio = health_ratio(health, max_health);
+
+        for &child in children.iter() {
+            if let Ok(mut transform) = bars.get_mut(child) {
+                *transform = bar_fill_transform(ratio, display.offset);
+            }
+            if let Ok(mut text) = texts.get_mut(child) {
+                text.sections[0].value = health_text(health);
+            }
+        }
+    }
+}
+
+fn spawn_damage_numbers(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    fonts: Res<FontAssets>,
+    mut event_deal_damage: EventReader<DealDamageEvent>,
+    q: Query<&Transform, With<Health>>,
+) {
+    for event in event_deal_damage.iter() {
+        let transform = match q.get(event.entity) {
+            Ok(transform) if settings.damage_numbers => transform,
+            _ => continue,
+        };
+        let style = TextStyle {
+            font: fonts.fira_sans.clone(),
+            font_size: 12.,
+            color: Color::rgb(1., 0.9, 0.3),
+        };
+        let alignment = TextAlignment {
+            vertical: VerticalAlign::Center,
+            horizontal: HorizontalAlign::Center,
+        };
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::with_section(format!("{}", event.amount.round()), style, alignment),
+                // above the sprites
+                transform: Transform::from_translation(
+                    (transform.translation.truncate() + Vec2::new(0., 20.)).extend(900.),
+                ),
+                ..Default::default()
+            })
+            .insert(DamageNumber {
+                timer: Timer::from_seconds(DAMAGE_NUMBER_DURATION, false),
+            });
+    }
+}
+
+fn float_damage_numbers(
+    mut commands: Commands,
+    time: Res<SimTime>,
+    mut q: Query<(Entity, &mut DamageNumber, &mut Transform, &mut Text)>,
+) {
+    for (e, mut number, mut transform, mut text) in q.iter_mut() {
+        number.timer.tick(time.delta());
+        if number.timer.finished() {
+            commands.entity(e).despawn();
+            continue;
+        }
+        transform.translation.y += DAMAGE_NUMBER_SPEED * time.delta_seconds();
+        text.sections[0]
+            .style
+            .color
+            .set_a(1. - number.timer.percent());
+    }
+}
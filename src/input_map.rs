@@ -0,0 +1,354 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::utils::config_path;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const INPUT_MAP_FILE: &str = "input.ron";
+
+/// Logical actions of the game, each bound to any number of physical inputs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Pause,
+    Confirm,
+    Cancel,
+    Dash,
+    Ability1,
+    Ability2,
+    Ability3,
+    Ability4,
+}
+
+impl Action {
+    pub const ALL: [Action; 12] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Pause,
+        Action::Confirm,
+        Action::Cancel,
+        Action::Dash,
+        Action::Ability1,
+        Action::Ability2,
+        Action::Ability3,
+        Action::Ability4,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Pause => "Pause",
+            Action::Confirm => "Confirm",
+            Action::Cancel => "Cancel",
+            Action::Dash => "Dash",
+            Action::Ability1 => "Ability 1",
+            Action::Ability2 => "Ability 2",
+            Action::Ability3 => "Ability 3",
+            Action::Ability4 => "Ability 4",
+        }
+    }
+}
+
+/// A physical key or button
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// A button of the active gamepad
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => format!("Mouse {:?}", button),
+            Binding::Gamepad(button) => format!("Pad {:?}", button),
+        }
+    }
+
+    pub fn pressed(&self, inputs: Inputs) -> bool {
+        match *self {
+            Binding::Key(key) => inputs.keys.pressed(key),
+            Binding::Mouse(button) => inputs.mouse.pressed(button),
+            Binding::Gamepad(button) => inputs.gamepad.map_or(false, |gamepad| {
+                inputs
+                    .gamepad_buttons
+                    .pressed(GamepadButton(gamepad, button))
+            }),
+        }
+    }
+
+    pub fn just_pressed(&self, inputs: Inputs) -> bool {
+        match *self {
+            Binding::Key(key) => inputs.keys.just_pressed(key),
+            Binding::Mouse(button) => inputs.mouse.just_pressed(button),
+            Binding::Gamepad(button) => inputs.gamepad.map_or(false, |gamepad| {
+                inputs
+                    .gamepad_buttons
+                    .just_pressed(GamepadButton(gamepad, button))
+            }),
+        }
+    }
+
+    pub fn just_released(&self, inputs: Inputs) -> bool {
+        match *self {
+            Binding::Key(key) => inputs.keys.just_released(key),
+            Binding::Mouse(button) => inputs.mouse.just_released(button),
+            Binding::Gamepad(button) => inputs.gamepad.map_or(false, |gamepad| {
+                inputs
+                    .gamepad_buttons
+                    .just_released(GamepadButton(gamepad, button))
+            }),
+        }
+    }
+
+    /// Any key or button pressed this frame, used to rebind an action
+    pub fn any_just_pressed(inputs: Inputs) -> Option<Binding> {
+        inputs
+            .keys
+            .get_just_pressed()
+            .next()
+            .map(|&key| Binding::Key(key))
+            .or_else(|| {
+                inputs
+                    .mouse
+                    .get_just_pressed()
+                    .next()
+                    .map(|&button| Binding::Mouse(button))
+            })
+            .or_else(|| {
+                inputs
+                    .gamepad_buttons
+                    .get_just_pressed()
+                    .find(|button| Some(button.0) == inputs.gamepad)
+                    .map(|button| Binding::Gamepad(button.1))
+            })
+    }
+}
+
+/// The input devices the bindings are read from
+#[derive(Clone, Copy)]
+pub struct Inputs<'a> {
+    pub keys: &'a Input<KeyCode>,
+    pub mouse: &'a Input<MouseButton>,
+    pub gamepad_buttons: &'a Input<GamepadButton>,
+    /// Only the buttons of this gamepad are read
+    pub gamepad: Option<Gamepad>,
+}
+
+/// Gamepad used by the player, the first one connected
+/// When it is unplugged, the next connected gamepad takes over
+#[derive(Default, Debug)]
+pub struct ActiveGamepad {
+    pub gamepad: Option<Gamepad>,
+    pub connected: Vec<Gamepad>,
+}
+
+/// How the position of an analog stick is turned into movement
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct StickSettings {
+    /// Positions closer to the center are ignored, so that a worn stick doesn't drift
+    pub deadzone: f32,
+    /// Positions further from the center are full tilt
+    pub outer_deadzone: f32,
+    /// Response curve between the deadzones, above 1 gives finer control at low tilt
+    pub exponent: f32,
+}
+
+impl Default for StickSettings {
+    fn default() -> Self {
+        StickSettings {
+            deadzone: 0.15,
+            outer_deadzone: 0.95,
+            exponent: 1.5,
+        }
+    }
+}
+
+impl StickSettings {
+    /// Movement for a raw stick position, keeping its direction
+    /// The magnitude is rescaled between the deadzones and can be below 1 to walk slowly,
+    /// `None` inside the deadzone
+    pub fn apply(&self, stick: Vec2) -> Option<Vec2> {
+        let tilt = stick.length();
+        if tilt <= self.deadzone {
+            return None;
+        }
+        let range = (self.outer_deadzone - self.deadzone).max(f32::EPSILON);
+        let magnitude = ((tilt - self.deadzone) / range).min(1.).powf(self.exponent);
+        Some(stick / tilt * magnitude)
+    }
+}
+
+/// Whether the weapons fire where the player aims
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AimMode {
+    /// Weapons pick their targets on their own
+    Auto,
+    /// Weapons fire toward the mouse cursor or the right stick,
+    /// and pick their targets on their own while the player doesn't aim
+    Manual,
+}
+
+impl Default for AimMode {
+    fn default() -> Self {
+        AimMode::Auto
+    }
+}
+
+impl AimMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AimMode::Auto => "Auto-aim",
+            AimMode::Manual => "Mouse / right stick",
+        }
+    }
+}
+
+/// Physical inputs bound to each action, saved in the config directory
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InputMap {
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+    #[serde(default)]
+    pub stick: StickSettings,
+    #[serde(default)]
+    pub aim: AimMode,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use Binding::{Gamepad as Pad, Key};
+        use GamepadButtonType::*;
+
+        let bindings = Action::ALL
+            .iter()
+            .map(|&action| {
+                let bindings = match action {
+                    Action::MoveUp => vec![Key(KeyCode::W), Key(KeyCode::Up), Pad(DPadUp)],
+                    Action::MoveDown => {
+                        vec![Key(KeyCode::S), Key(KeyCode::Down), Pad(DPadDown)]
+                    }
+                    Action::MoveLeft => {
+                        vec![Key(KeyCode::A), Key(KeyCode::Left), Pad(DPadLeft)]
+                    }
+                    Action::MoveRight => {
+                        vec![Key(KeyCode::D), Key(KeyCode::Right), Pad(DPadRight)]
+                    }
+                    Action::Pause => vec![Key(KeyCode::Escape), Key(KeyCode::P), Pad(Start)],
+                    Action::Confirm => {
+                        vec![Key(KeyCode::Return), Key(KeyCode::Space), Pad(South)]
+                    }
+                    Action::Cancel => vec![Key(KeyCode::Escape), Key(KeyCode::Back), Pad(East)],
+                    Action::Dash => vec![Key(KeyCode::LShift), Pad(RightTrigger)],
+                    // the abilities also pick the upgrades offered on level-up
+                    Action::Ability1 => vec![Key(KeyCode::Q), Key(KeyCode::Key1), Pad(West)],
+                    Action::Ability2 => vec![Key(KeyCode::E), Key(KeyCode::Key2), Pad(North)],
+                    Action::Ability3 => {
+                        vec![Key(KeyCode::R), Key(KeyCode::Key3), Pad(LeftTrigger)]
+                    }
+                    Action::Ability4 => {
+                        vec![Key(KeyCode::F), Key(KeyCode::Key4), Pad(LeftTrigger2)]
+                    }
+                };
+                (action, bindings)
+            })
+            .collect();
+
+        InputMap {
+            bindings,
+            stick: StickSettings::default(),
+            aim: AimMode::default(),
+        }
+    }
+}
+
+impl InputMap {
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings
+            .get(&action)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn pressed(&self, action: Action, inputs: Inputs) -> bool {
+        self.bindings(action).iter().any(|b| b.pressed(inputs))
+    }
+
+    pub fn just_pressed(&self, action: Action, inputs: Inputs) -> bool {
+        self.bindings(action).iter().any(|b| b.just_pressed(inputs))
+    }
+
+    pub fn just_released(&self, action: Action, inputs: Inputs) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|b| b.just_released(inputs))
+    }
+
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        self.bindings.insert(action, Vec::new());
+    }
+
+    fn path() -> Option<PathBuf> {
+        config_path(INPUT_MAP_FILE)
+    }
+
+    /// The saved input map, or the default bindings if there is none
+    pub fn load() -> Self {
+        InputMap::path().map_or_else(InputMap::default, |path| InputMap::load_from(&path))
+    }
+
+    /// The input map saved in `path`, or the default bindings if the file is missing or invalid
+    /// Actions missing from the file keep their default bindings
+    pub fn load_from(path: &Path) -> Self {
+        let mut map = InputMap::default();
+        if !path.exists() {
+            return map;
+        }
+        match fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| ron::de::from_str::<InputMap>(&file).map_err(anyhow::Error::from))
+        {
+            Ok(saved) => {
+                map.bindings.extend(saved.bindings);
+                map.stick = saved.stick;
+                map.aim = saved.aim;
+            }
+            Err(error) => error!("Failed to load {}: {}", path.display(), error),
+        }
+        map
+    }
+
+    pub fn save(&self) {
+        if let Some(path) = InputMap::path() {
+            self.save_to(&path);
+        }
+    }
+
+    pub fn save_to(&self, path: &Path) {
+        let pretty = ron::ser::PrettyConfig::default();
+        if let Err(error) = ron::ser::to_string_pretty(self, pretty)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| fs::write(path, file).map_err(anyhow::Error::from))
+        {
+            error!("Failed to save {}: {}", path.display(), error);
+        }
+    }
+}
@@ -1,18 +1,57 @@
 pub mod actions;
+pub mod animation;
+pub mod archetype;
 pub mod audio;
 pub mod collide_aabb;
+pub mod controls;
 pub mod enemy;
+pub mod game_over;
+pub mod health_display;
+pub mod input_map;
 pub mod loading;
 pub mod menu;
+pub mod music;
+pub mod pause;
 pub mod player;
+pub mod pool;
+pub mod progression;
+pub mod projectile;
+pub mod replay;
+pub mod rng;
+pub mod settings;
+pub mod sfx;
+pub mod spatial;
+pub mod timestep;
+pub mod upgrades;
 pub mod utils;
+pub mod waves;
+pub mod weapon;
 
-use crate::actions::ActionsPlugin;
+use crate::actions::{Actions, ActionsPlugin};
+use crate::animation::AnimationLibraryPlugin;
+use crate::archetype::ArchetypePlugin;
 use crate::audio::InternalAudioPlugin;
+use crate::controls::ControlsPlugin;
 use crate::enemy::EnemyPlugin;
+use crate::game_over::GameOverPlugin;
+use crate::health_display::HealthDisplayPlugin;
+use crate::input_map::{ActiveGamepad, InputMap};
 use crate::loading::LoadingPlugin;
 use crate::menu::MenuPlugin;
+use crate::music::MusicPlugin;
+use crate::pause::PausePlugin;
 use crate::player::PlayerPlugin;
+use crate::progression::ProgressionPlugin;
+use crate::projectile::ProjectilePlugin;
+use crate::replay::ReplayPlugin;
+use crate::rng::RngPlugin;
+use crate::settings::{Settings, SettingsPlugin};
+use crate::sfx::SfxPlugin;
+use crate::spatial::SpatialPlugin;
+use crate::timestep::TimestepPlugin;
+use crate::upgrades::UpgradePlugin;
+use crate::waves::WavePlugin;
+use crate::weapon::WeaponPlugin;
 
 use benimator::AnimationPlugin;
 use bevy::app::App;
@@ -29,27 +68,34 @@ struct GameConfiguration {
 // See https://bevy-cheatbook.github.io/programming/states.html
 // Or https://github.com/bevyengine/bevy/blob/main/examples/ecs/state.rs
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
-enum GameState {
+pub enum GameState {
     // During the loading State the LoadingPlugin will load our assets
     Loading,
     // During this State the actual game logic is executed
     Playing,
     // Here the menu is drawn and waiting for player interaction
     Menu,
+    // Pushed on top of Playing while the player handles a level-up
+    LevelUp,
+    // Pushed on top of Playing while the game is paused
+    Paused,
+    // Results of the last run, waiting for a retry or a return to the menu
+    GameOver,
+    // Rebinding screen, opened from the settings
+    Controls,
+    // Settings screen, opened from the menu or pushed on top of Paused
+    Settings,
 }
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_state(GameState::Loading)
+        app.add_plugin(GameLogicPlugin)
+            .insert_resource(InputMap::load())
             .add_plugin(LoadingPlugin)
-            .add_plugin(MenuPlugin)
             .add_plugin(ActionsPlugin)
-            .add_plugin(InternalAudioPlugin)
-            .add_plugin(PlayerPlugin)
-            .add_plugin(EnemyPlugin)
-            .add_plugin(AnimationPlugin::default());
+            .add_plugin(InternalAudioPlugin);
 
         #[cfg(debug_assertions)]
         {
@@ -58,3 +104,43 @@ impl Plugin for GamePlugin {
         }
     }
 }
+
+/// Everything that runs without a window, a renderer, an audio device or the asset files
+/// The `GamePlugin` adds the asset loading, saved input map, player input and audio on top of it,
+/// the saved settings are inserted by `main` since they also describe the window,
+/// headless simulations provide the asset collections and drive `Actions` themselves,
+/// or add the `ActionsPlugin` and feed it synthetic input events
+pub struct GameLogicPlugin;
+
+impl Plugin for GameLogicPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state(GameState::Loading)
+            .insert_resource(GameConfiguration { scale: 3. })
+            .init_resource::<Actions>()
+            .init_resource::<InputMap>()
+            .init_resource::<ActiveGamepad>()
+            .init_resource::<Settings>()
+            .add_plugin(TimestepPlugin)
+            .add_plugin(RngPlugin)
+            .add_plugin(ReplayPlugin)
+            .add_plugin(ArchetypePlugin)
+            .add_plugin(AnimationLibraryPlugin)
+            .add_plugin(WavePlugin)
+            .add_plugin(MenuPlugin)
+            .add_plugin(SettingsPlugin)
+            .add_plugin(ControlsPlugin)
+            .add_plugin(PlayerPlugin)
+            .add_plugin(EnemyPlugin)
+            .add_plugin(SpatialPlugin)
+            .add_plugin(WeaponPlugin)
+            .add_plugin(ProjectilePlugin)
+            .add_plugin(ProgressionPlugin)
+            .add_plugin(UpgradePlugin)
+            .add_plugin(GameOverPlugin)
+            .add_plugin(PausePlugin)
+            .add_plugin(HealthDisplayPlugin)
+            .add_plugin(SfxPlugin)
+            .add_plugin(MusicPlugin)
+            .add_plugin(AnimationPlugin::default());
+    }
+}
@@ -1,7 +1,9 @@
+use crate::animation::AnimationDefs;
+use crate::archetype::EnemyArchetypes;
+use crate::waves::WaveScript;
 use crate::GameState;
 use bevy::prelude::*;
 use bevy_asset_loader::{AssetCollection, AssetLoader};
-use bevy_kira_audio::AudioSource;
 
 pub struct LoadingPlugin;
 
@@ -12,8 +14,8 @@ impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut App) {
         AssetLoader::new(GameState::Loading)
             .with_collection::<FontAssets>()
-            .with_collection::<AudioAssets>()
             .with_collection::<TextureAssets>()
+            .with_collection::<DataAssets>()
             .continue_to_state(GameState::Menu)
             .build(app);
     }
@@ -29,9 +31,13 @@ pub struct FontAssets {
 }
 
 #[derive(AssetCollection)]
-pub struct AudioAssets {
-    #[asset(path = "audio/flying.ogg")]
-    pub flying: Handle<AudioSource>,
+pub struct DataAssets {
+    #[asset(path = "data/archetypes.enemies.ron")]
+    pub enemies: Handle<EnemyArchetypes>,
+    #[asset(path = "data/default.waves.ron")]
+    pub waves: Handle<WaveScript>,
+    #[asset(path = "data/default.animations.ron")]
+    pub animations: Handle<AnimationDefs>,
 }
 
 #[derive(AssetCollection)]
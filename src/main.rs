@@ -1,21 +1,34 @@
 // disable console on windows for release builds
 //#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use bevy::prelude::{App, ClearColor, Color, WindowDescriptor};
+#[cfg(feature = "dev")]
+use bevy::asset::AssetServerSettings;
+use bevy::prelude::{App, ClearColor, Color};
 use bevy::DefaultPlugins;
+use bevy_game::replay::ReplaySettings;
+use bevy_game::rng::RngSeed;
+use bevy_game::settings::Settings;
 use bevy_game::GamePlugin;
 
 fn main() {
-    App::new()
-        //.insert_resource(Msaa { samples: 1 })
-        .insert_resource(ClearColor(Color::rgb(0.4, 0.4, 0.4)))
-        .insert_resource(WindowDescriptor {
-            width: 800.,
-            height: 600.,
-            title: "Bevy game".to_string(), // ToDo
-            ..Default::default()
-        })
-        .add_plugins(DefaultPlugins)
-        .add_plugin(GamePlugin)
-        .run();
+    let mut app = App::new();
+    let settings = Settings::load();
+    //app.insert_resource(Msaa { samples: 1 })
+    app.insert_resource(ClearColor(Color::rgb(0.4, 0.4, 0.4)))
+        .insert_resource(settings.window_descriptor("Bevy game")) // ToDo
+        .insert_resource(settings);
+
+    // hot-reload the data files (enemy archetypes...) while developing
+    #[cfg(feature = "dev")]
+    app.insert_resource(AssetServerSettings {
+        watch_for_changes: true,
+        ..Default::default()
+    });
+
+    // `--seed <u64>` replays the same runs
+    app.insert_resource(RngSeed::from_args());
+    // `--record <path>` saves the runs, `--replay <path>` plays one back
+    app.insert_resource(ReplaySettings::from_args());
+
+    app.add_plugins(DefaultPlugins).add_plugin(GamePlugin).run();
 }
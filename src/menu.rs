@@ -1,22 +1,33 @@
+use crate::input_map::{Action, ActiveGamepad, InputMap, Inputs};
 use crate::loading::FontAssets;
+use crate::utils::despawn_with;
 use crate::GameState;
 use bevy::prelude::*;
 
 pub struct MenuPlugin;
 
-/// This plugin is responsible for the game menu (containing only one button...)
+/// This plugin is responsible for the game menu (play, or change the settings)
+/// `Action::Confirm` also starts a run, so that the game can be played with a gamepad only
 /// The menu is only drawn during the State `GameState::Menu` and is removed when that state is exited
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ButtonColors>()
+            .add_startup_system(spawn_ui_camera)
             .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(setup_menu))
-            .add_system_set(SystemSet::on_update(GameState::Menu).with_system(click_play_button));
+            .add_system_set(
+                SystemSet::on_update(GameState::Menu)
+                    .with_system(click_menu_button)
+                    .with_system(play_on_confirm),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Menu).with_system(despawn_with::<MenuUi>),
+            );
     }
 }
 
-struct ButtonColors {
-    normal: UiColor,
-    hovered: UiColor,
+pub(crate) struct ButtonColors {
+    pub normal: UiColor,
+    pub hovered: UiColor,
 }
 
 impl Default for ButtonColors {
@@ -29,61 +40,89 @@ impl Default for ButtonColors {
 }
 
 #[derive(Component)]
-struct PlayButton;
+struct MenuUi;
+
+#[derive(Component, Clone, Copy)]
+enum MenuButton {
+    Play,
+    Settings,
+}
+
+fn spawn_ui_camera(mut commands: Commands) {
+    commands.spawn_bundle(UiCameraBundle::default());
+}
 
 fn setup_menu(
     mut commands: Commands,
     font_assets: Res<FontAssets>,
     button_colors: Res<ButtonColors>,
 ) {
-    commands.spawn_bundle(UiCameraBundle::default());
     commands
-        .spawn_bundle(ButtonBundle {
+        .spawn_bundle(NodeBundle {
             style: Style {
-                size: Size::new(Val::Px(120.0), Val::Px(50.0)),
-                margin: Rect::all(Val::Auto),
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
                 justify_content: JustifyContent::Center,
                 align_items: AlignItems::Center,
                 ..Default::default()
             },
-            color: button_colors.normal,
+            color: Color::NONE.into(),
             ..Default::default()
         })
-        .insert(PlayButton)
+        .insert(MenuUi)
         .with_children(|parent| {
-            parent.spawn_bundle(TextBundle {
-                text: Text {
-                    sections: vec![TextSection {
-                        value: "Play".to_string(),
-                        style: TextStyle {
-                            font: font_assets.fira_sans.clone(),
-                            font_size: 40.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
+            for (button, label, width) in [
+                (MenuButton::Play, "Play", 120.),
+                (MenuButton::Settings, "Settings", 180.),
+            ] {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(width), Val::Px(50.0)),
+                            margin: Rect::all(Val::Px(10.)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
                         },
-                    }],
-                    alignment: Default::default(),
-                },
-                ..Default::default()
-            });
+                        color: button_colors.normal,
+                        ..Default::default()
+                    })
+                    .insert(button)
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle {
+                            text: Text {
+                                sections: vec![TextSection {
+                                    value: label.to_string(),
+                                    style: TextStyle {
+                                        font: font_assets.fira_sans.clone(),
+                                        font_size: 40.0,
+                                        color: Color::rgb(0.9, 0.9, 0.9),
+                                    },
+                                }],
+                                alignment: Default::default(),
+                            },
+                            ..Default::default()
+                        });
+                    });
+            }
         });
 }
 
-type ButtonInteraction<'a> = (Entity, &'a Interaction, &'a mut UiColor, &'a Children);
+type MenuButtonInteraction<'a> = (&'a MenuButton, &'a Interaction, &'a mut UiColor);
 
-fn click_play_button(
-    mut commands: Commands,
+fn click_menu_button(
     button_colors: Res<ButtonColors>,
     mut state: ResMut<State<GameState>>,
-    mut interaction_query: Query<ButtonInteraction, (Changed<Interaction>, With<Button>)>,
-    text_query: Query<Entity, With<Text>>,
+    mut interaction_query: Query<MenuButtonInteraction, Changed<Interaction>>,
 ) {
-    for (button, interaction, mut color, children) in interaction_query.iter_mut() {
-        let text = text_query.get(children[0]).unwrap();
+    for (button, interaction, mut color) in interaction_query.iter_mut() {
         match *interaction {
             Interaction::Clicked => {
-                commands.entity(button).despawn();
-                commands.entity(text).despawn();
-                state.set(GameState::Playing).unwrap();
+                let next = match button {
+                    MenuButton::Play => GameState::Playing,
+                    MenuButton::Settings => GameState::Settings,
+                };
+                let _ = state.set(next);
             }
             Interaction::Hovered => {
                 *color = button_colors.hovered;
@@ -94,3 +133,22 @@ fn click_play_button(
         }
     }
 }
+
+fn play_on_confirm(
+    input_map: Res<InputMap>,
+    active: Res<ActiveGamepad>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let inputs = Inputs {
+        keys: &keyboard_input,
+        mouse: &mouse_input,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad: active.gamepad,
+    };
+    if input_map.just_pressed(Action::Confirm, inputs) {
+        let _ = state.set(GameState::Playing);
+    }
+}
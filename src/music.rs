@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::f32::consts::FRAC_PI_2;
+
+use crate::enemy::{Alive, Enemy};
+use crate::player::Player;
+use crate::waves::Boss;
+use crate::GameState;
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+
+pub struct MusicPlugin;
+
+/// Enemies closer to the player than this count toward the intensity of the music
+const MUSIC_RADIUS: f32 = 250.;
+/// Enemies around the player for the intense layer to play alone
+const INTENSE_ENEMIES: f32 = 30.;
+/// Intensity gained or lost per second, so that a crossfade lasts at least 2 seconds
+const CROSSFADE_SPEED: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Playlist {
+    Menu,
+    Playing,
+    Boss,
+    GameOver,
+}
+
+impl Playlist {
+    /// Playlist of the state, `None` keeps the current one
+    pub fn for_state(state: &GameState, boss_alive: bool) -> Option<Playlist> {
+        match state {
+            GameState::Menu => Some(Playlist::Menu),
+            GameState::Playing | GameState::LevelUp | GameState::Paused if boss_alive => {
+                Some(Playlist::Boss)
+            }
+            GameState::Playing | GameState::LevelUp | GameState::Paused => Some(Playlist::Playing),
+            GameState::GameOver => Some(Playlist::GameOver),
+            // the settings keep the music of the menu or the run they were opened from
+            GameState::Loading | GameState::Settings | GameState::Controls => None,
+        }
+    }
+}
+
+/// A looped track, made of a calm layer and an optional intense layer playing in sync
+#[derive(Clone, Debug, PartialEq)]
+pub struct MusicTrack {
+    pub calm: String,
+    pub intense: Option<String>,
+}
+
+impl MusicTrack {
+    pub fn new(calm: &str, intense: Option<&str>) -> Self {
+        MusicTrack {
+            calm: calm.to_string(),
+            intense: intense.map(str::to_string),
+        }
+    }
+}
+
+/// Tracks of each playlist, played in turn every time the playlist starts
+/// Playlists without tracks are silent
+pub struct MusicLibrary(pub HashMap<Playlist, Vec<MusicTrack>>);
+
+/// Only `flying.ogg` is shipped in `assets/audio` yet: it plays during the runs and the boss fights,
+/// the other playlists and the intense layers wait for their files
+impl Default for MusicLibrary {
+    fn default() -> Self {
+        MusicLibrary(HashMap::from([
+            (
+                Playlist::Playing,
+                vec![MusicTrack::new("audio/flying.ogg", None)],
+            ),
+            (
+                Playlist::Boss,
+                vec![MusicTrack::new("audio/flying.ogg", None)],
+            ),
+        ]))
+    }
+}
+
+impl MusicLibrary {
+    /// All the music files, so that they can be loaded before they are played
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.0
+            .values()
+            .flatten()
+            .flat_map(|track| std::iter::once(&track.calm).chain(&track.intense))
+            .map(String::as_str)
+    }
+}
+
+/// Decides what music plays and how intense it is, the audio plugin follows it
+#[derive(Default)]
+pub struct MusicDirector {
+    playlist: Option<Playlist>,
+    track: Option<MusicTrack>,
+    /// Next track of each playlist
+    next: HashMap<Playlist, usize>,
+    /// Crossfade between the calm (0) and intense (1) layers
+    intensity: f32,
+}
+
+impl MusicDirector {
+    pub fn playlist(&self) -> Option<Playlist> {
+        self.playlist
+    }
+
+    pub fn track(&self) -> Option<&MusicTrack> {
+        self.track.as_ref()
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    /// Starts the next track of the playlist, unless the playlist is already playing
+    pub fn set_playlist(&mut self, playlist: Playlist, library: &MusicLibrary) {
+        if self.playlist == Some(playlist) {
+            return;
+        }
+        self.playlist = Some(playlist);
+        self.intensity = 0.;
+        self.track = match library.0.get(&playlist) {
+            Some(tracks) if !tracks.is_empty() => {
+                let next = self.next.entry(playlist).or_default();
+                let track = tracks[*next % tracks.len()].clone();
+                *next += 1;
+                Some(track)
+            }
+            _ => None,
+        };
+    }
+
+    /// Moves the intensity toward the number of enemies around the player, `dt` in seconds
+    pub fn update_intensity(&mut self, nearby_enemies: usize, dt: f32) {
+        let target = (nearby_enemies as f32 / INTENSE_ENEMIES).min(1.);
+        let step = CROSSFADE_SPEED * dt;
+        self.intensity += (target - self.intensity).clamp(-step, step);
+    }
+
+    /// Volumes of the calm and intense layers, crossfaded at constant power
+    /// The calm layer plays alone when the track has no intense layer
+    pub fn layer_volumes(&self) -> (f32, f32) {
+        match self.track {
+            Some(MusicTrack {
+                intense: Some(_), ..
+            }) => {
+                let angle = self.intensity * FRAC_PI_2;
+                (angle.cos(), angle.sin())
+            }
+            _ => (1., 0.),
+        }
+    }
+}
+
+/// This plugin drives the `MusicDirector` from the game state and the enemies around the player
+/// It doesn't play anything, the audio plugin follows the director
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MusicLibrary>()
+            .init_resource::<MusicDirector>()
+            .add_system(direct_music);
+    }
+}
+
+fn direct_music(
+    time: Res<Time>,
+    state: Res<State<GameState>>,
+    library: Res<MusicLibrary>,
+    mut director: ResMut<MusicDirector>,
+    player: Query<&Transform, With<Player>>,
+    enemies: Query<(&Transform, Option<&Boss>), (With<Enemy>, With<Alive>)>,
+) {
+    let boss_alive = enemies.iter().any(|(_, boss)| boss.is_some());
+    if let Some(playlist) = Playlist::for_state(state.current(), boss_alive) {
+        director.set_playlist(playlist, &library);
+    }
+
+    let nearby_enemies = match player.get_single() {
+        Ok(player) => enemies
+            .iter()
+            .filter(|(enemy, _)| {
+                enemy.translation.xy().distance(player.translation.xy()) < MUSIC_RADIUS
+            })
+            .count(),
+        Err(_) => 0,
+    };
+    director.update_intensity(nearby_enemies, time.delta_seconds());
+}
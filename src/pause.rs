@@ -0,0 +1,248 @@
+use crate::input_map::{Action, ActiveGamepad, InputMap, Inputs};
+use crate::loading::FontAssets;
+use crate::menu::ButtonColors;
+use crate::utils::despawn_with;
+use crate::GameState;
+use benimator::Play;
+use bevy::prelude::*;
+
+pub struct PausePlugin;
+
+#[derive(Component)]
+struct PauseUi;
+
+#[derive(Component, Clone, Copy, PartialEq)]
+enum PauseButton {
+    Resume,
+    Settings,
+    Restart,
+    Quit,
+}
+
+const PAUSE_BUTTONS: [PauseButton; 4] = [
+    PauseButton::Resume,
+    PauseButton::Settings,
+    PauseButton::Restart,
+    PauseButton::Quit,
+];
+
+impl PauseButton {
+    fn label(&self) -> &'static str {
+        match self {
+            PauseButton::Resume => "Resume",
+            PauseButton::Settings => "Settings",
+            PauseButton::Restart => "Restart",
+            PauseButton::Quit => "Quit to Menu",
+        }
+    }
+}
+
+/// Index of the button highlighted by keyboard, gamepad or mouse
+#[derive(Default)]
+struct SelectedPauseButton(usize);
+
+/// Animation stopped while the run is paused, it plays again on resume
+#[derive(Component)]
+struct FrozenAnimation;
+
+/// This plugin pauses the run with `Action::Pause`, by pushing `GameState::Paused` over `Playing`
+/// Gameplay systems and their timers only run during `GameState::Playing`, so they are frozen as is,
+/// sprite animations are stopped whenever a state is pushed over `Playing` (pause or level-up)
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedPauseButton>()
+            .add_system(toggle_pause)
+            .add_system_set(SystemSet::on_pause(GameState::Playing).with_system(freeze_animations))
+            .add_system_set(
+                SystemSet::on_resume(GameState::Playing).with_system(unfreeze_animations),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(setup_pause_menu))
+            .add_system_set(SystemSet::on_resume(GameState::Paused).with_system(setup_pause_menu))
+            .add_system_set(
+                SystemSet::on_update(GameState::Paused).with_system(navigate_pause_menu),
+            )
+            .add_system_set(
+                SystemSet::on_pause(GameState::Paused).with_system(despawn_with::<PauseUi>),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Paused).with_system(despawn_with::<PauseUi>),
+            );
+    }
+}
+
+/// Runs in every state, a single system so that the same key press can't pause and resume
+/// A state entered during the frame is left alone, the key that led to it is still pressed:
+/// cancelling the settings would close the pause menu under them too
+fn toggle_pause(
+    input_map: Res<InputMap>,
+    active: Res<ActiveGamepad>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut state: ResMut<State<GameState>>,
+    mut last_state: Local<Option<GameState>>,
+) {
+    let entered = last_state.as_ref() != Some(state.current());
+    *last_state = Some(state.current().clone());
+    if entered {
+        return;
+    }
+    let inputs = Inputs {
+        keys: &keyboard_input,
+        mouse: &mouse_input,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad: active.gamepad,
+    };
+    let pause = input_map.just_pressed(Action::Pause, inputs);
+
+    match state.current() {
+        GameState::Playing if pause => {
+            let _ = state.push(GameState::Paused);
+        }
+        GameState::Paused if pause || input_map.just_pressed(Action::Cancel, inputs) => {
+            let _ = state.pop();
+        }
+        _ => {}
+    }
+}
+
+fn freeze_animations(mut commands: Commands, playing: Query<Entity, With<Play>>) {
+    for e in playing.iter() {
+        commands.entity(e).remove::<Play>().insert(FrozenAnimation);
+    }
+}
+
+fn unfreeze_animations(mut commands: Commands, frozen: Query<Entity, With<FrozenAnimation>>) {
+    for e in frozen.iter() {
+        commands.entity(e).remove::<FrozenAnimation>().insert(Play);
+    }
+}
+
+fn setup_pause_menu(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    button_colors: Res<ButtonColors>,
+    mut selected: ResMut<SelectedPauseButton>,
+) {
+    selected.0 = 0;
+    let text_style = |font_size| TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: Color::rgba(0., 0., 0., 0.5).into(),
+            ..Default::default()
+        })
+        .insert(PauseUi)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(10.)),
+                    ..Default::default()
+                },
+                text: Text::with_section("Paused", text_style(40.), Default::default()),
+                ..Default::default()
+            });
+
+            for (i, button) in PAUSE_BUTTONS.iter().enumerate() {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(240.0), Val::Px(50.0)),
+                            margin: Rect::all(Val::Px(5.)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        color: if i == selected.0 {
+                            button_colors.hovered
+                        } else {
+                            button_colors.normal
+                        },
+                        ..Default::default()
+                    })
+                    .insert(*button)
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                button.label(),
+                                text_style(32.),
+                                Default::default(),
+                            ),
+                            ..Default::default()
+                        });
+                    });
+            }
+        });
+}
+
+type PauseButtonInteraction<'a> = (&'a PauseButton, &'a Interaction, &'a mut UiColor);
+
+#[allow(clippy::too_many_arguments)]
+fn navigate_pause_menu(
+    input_map: Res<InputMap>,
+    active: Res<ActiveGamepad>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    button_colors: Res<ButtonColors>,
+    mut selected: ResMut<SelectedPauseButton>,
+    mut state: ResMut<State<GameState>>,
+    clicked: Query<(&PauseButton, &Interaction), Changed<Interaction>>,
+    mut buttons: Query<PauseButtonInteraction>,
+) {
+    let inputs = Inputs {
+        keys: &keyboard_input,
+        mouse: &mouse_input,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad: active.gamepad,
+    };
+    let n = PAUSE_BUTTONS.len();
+    let mut chosen = None;
+
+    if input_map.just_pressed(Action::MoveUp, inputs) {
+        selected.0 = (selected.0 + n - 1) % n;
+    }
+    if input_map.just_pressed(Action::MoveDown, inputs) {
+        selected.0 = (selected.0 + 1) % n;
+    }
+    if input_map.just_pressed(Action::Confirm, inputs) {
+        chosen = Some(PAUSE_BUTTONS[selected.0]);
+    }
+
+    for (button, interaction) in clicked.iter() {
+        let i = PAUSE_BUTTONS.iter().position(|b| b == button).unwrap_or(0);
+        match *interaction {
+            Interaction::Clicked => chosen = Some(*button),
+            Interaction::Hovered => selected.0 = i,
+            Interaction::None => {}
+        }
+    }
+
+    for (button, _interaction, mut color) in buttons.iter_mut() {
+        *color = if *button == PAUSE_BUTTONS[selected.0] {
+            button_colors.hovered
+        } else {
+            button_colors.normal
+        };
+    }
+
+    let _ = match chosen {
+        Some(PauseButton::Resume) => state.pop(),
+        Some(PauseButton::Settings) => state.push(GameState::Settings),
+        // leaving `Playing` despawns the run, entering it again starts a new one
+        Some(PauseButton::Restart) => state.replace(GameState::Playing),
+        Some(PauseButton::Quit) => state.replace(GameState::Menu),
+        None => Ok(()),
+    };
+}
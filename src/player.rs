@@ -1,13 +1,28 @@
-use std::time::Duration;
+use std::collections::HashSet;
 
 use crate::actions::Actions;
+use crate::animation::{CharacterAnimator, CharacterState};
+use crate::archetype::EnemyArchetypes;
 use crate::collide_aabb::collide;
-use crate::enemy::{sprite_z, Alive, Corpse, Dead, Enemy};
-use crate::loading::{FontAssets, TextureAssets};
+use crate::enemy::{sprite_z, Alive, Archetype, Corpse, Dead, Enemy};
+use crate::health_display::HealthDisplay;
+use crate::loading::{DataAssets, TextureAssets};
+use crate::pool::{release, Pool, PoolPlugin, Poolable};
+use crate::progression::{Experience, Level, XpGainedEvent};
+use crate::settings::Settings;
+use crate::sfx::{PlaySfx, Sfx};
+use crate::spatial::{SpatialHash, UpdateSpatialHash};
+use crate::timestep::{playing, FixedTimestepAppExt, Interpolate, Interpolated, SimTime};
+use crate::utils::despawn_with;
+use crate::weapon::{Weapon, WeaponKind, WeaponModifiers};
 use crate::{GameConfiguration, GameState};
 use benimator::{Play, SpriteSheetAnimation};
+use bevy::diagnostic::DiagnosticId;
+use bevy::ecs::world::EntityMut;
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use rand::Rng;
 
 #[derive(Component)]
 pub struct MainCamera;
@@ -23,6 +38,9 @@ pub struct BaseMoveSpeed(pub f32);
 #[derive(Component)]
 pub struct Health(pub f32);
 
+#[derive(Component)]
+pub struct MaxHealth(pub f32);
+
 #[derive(Component)]
 pub struct HurtBox {
     pub pos: Vec2,
@@ -34,13 +52,37 @@ pub struct InvincibilityFrames {
     timer: Timer,
 }
 
+impl InvincibilityFrames {
+    pub fn from_seconds(duration: f32) -> Self {
+        InvincibilityFrames {
+            timer: Timer::from_seconds(duration, false),
+        }
+    }
+}
+
+/// Last direction the entity moved in, normalized
 #[derive(Component)]
-struct VFX;
+pub struct Facing(pub Vec2);
+
+/// Direction the entity aims its weapons at, normalized
+/// `None` lets the weapons pick their targets on their own
+#[derive(Component, Default)]
+pub struct Aim(pub Option<Vec2>);
 
 #[derive(Component)]
-pub struct AttackNearest {
-    pub damage: f32,
-    pub interval: Timer,
+pub struct VFX;
+
+/// Effects go back to the pool once their animation is over
+impl Poolable for VFX {
+    const NAME: &'static str = "vfx";
+    const FREE_DIAGNOSTIC: DiagnosticId =
+        DiagnosticId::from_u128(0x2c8e4f1b_7a3d_4e96_b05c_91d7e6a3f201);
+    const ACTIVE_DIAGNOSTIC: DiagnosticId =
+        DiagnosticId::from_u128(0x2c8e4f1b_7a3d_4e96_b05c_91d7e6a3f202);
+
+    fn reset(entity: &mut EntityMut) {
+        entity.remove_bundle::<(VFX, Handle<SpriteSheetAnimation>, Play)>();
+    }
 }
 
 #[derive(Component)]
@@ -51,6 +93,60 @@ pub struct XpGem {
     value: u32,
 }
 
+impl XpGem {
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+
+    pub fn tier(&self) -> GemTier {
+        GemTier::for_value(self.value)
+    }
+}
+
+/// Size of a gem, shown by its sprite
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GemTier {
+    Small,
+    Medium,
+    Large,
+}
+
+impl GemTier {
+    pub fn for_value(value: u32) -> Self {
+        match value {
+            0..=4 => GemTier::Small,
+            5..=24 => GemTier::Medium,
+            _ => GemTier::Large,
+        }
+    }
+
+    /// Index of the sprite in `TextureAssets::misc`
+    pub fn sprite_index(&self) -> usize {
+        match self {
+            GemTier::Small => 20,
+            GemTier::Medium => 21,
+            GemTier::Large => 22,
+        }
+    }
+}
+
+/// Gems closer than this to the player are attracted by it
+#[derive(Component)]
+pub struct PickupRadius(pub f32);
+
+/// Gems go back to the pool once gathered
+impl Poolable for XpGem {
+    const NAME: &'static str = "xp gem";
+    const FREE_DIAGNOSTIC: DiagnosticId =
+        DiagnosticId::from_u128(0x91f3a6d2_0b4e_4c7a_8e25_d3c1b7f45a01);
+    const ACTIVE_DIAGNOSTIC: DiagnosticId =
+        DiagnosticId::from_u128(0x91f3a6d2_0b4e_4c7a_8e25_d3c1b7f45a02);
+
+    fn reset(entity: &mut EntityMut) {
+        entity.remove_bundle::<(XpGem, Item, Interpolated)>();
+    }
+}
+
 #[derive(Component)]
 pub struct HitBox {
     pub pos: Vec2,
@@ -58,37 +154,95 @@ pub struct HitBox {
     pub damage: f32,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DamageKind {
+    Physical,
+    Magic,
+}
+
 pub struct DealDamageEvent {
-    entity: Entity,
+    pub entity: Entity,
+    pub source: Entity,
+    pub amount: f32,
+    pub kind: DamageKind,
 }
 
+/// Damage events are applied to `Health` by this label
+/// Systems reacting to the health of this tick should run after it
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub struct ApplyDamage;
+
 pub struct DieEvent {
-    entity: Entity,
+    pub entity: Entity,
+}
+
+/// Added when the player is hurt, the camera shakes by `trauma²` and it decays over time
+#[derive(Default)]
+pub struct CameraShake {
+    pub trauma: f32,
 }
 
+const SHAKE_TRAUMA_PER_HIT: f32 = 0.5;
+/// Trauma lost per second
+const SHAKE_DECAY: f32 = 1.5;
+/// Offset of the camera at full trauma, in pixels
+const SHAKE_MAX_OFFSET: f32 = 12.;
+
+/// Gems closer than this to the player are gathered
+const GATHER_RADIUS: f32 = 20.;
+/// Speed of the gems attracted by the player
+const GEM_SPEED: f32 = 500.;
+/// Gems on the ground before the gems close to each other are merged
+const MAX_GEMS: usize = 300;
+/// Gems closer than this to each other are merged
+const MERGE_RADIUS: f32 = 48.;
+
 /// This plugin handles player related stuff like movement
 /// Player logic is only active during the State `GameState::Playing`
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(
-            SystemSet::on_enter(GameState::Playing)
-                .with_system(spawn_player)
-                .with_system(spawn_camera),
-        )
-        .add_system_set(
-            SystemSet::on_update(GameState::Playing)
-                .with_system(move_player)
-                .with_system(move_camera)
-                .with_system(hurt_player)
-                .with_system(clean_animation_effects)
-                .with_system(trigger_attack_nearest)
-                .with_system(deal_damage)
-                .with_system(tick_invincibility_frames)
-                .with_system(handle_die)
-                .with_system(spawn_corpses)
-                .with_system(clean_corpses)
-                .with_system(gather_xp_gems),
-        );
+        app.init_resource::<CameraShake>()
+            .add_plugin(PoolPlugin::<VFX>::default())
+            .add_plugin(PoolPlugin::<XpGem>::default())
+            .add_fixed_event::<DealDamageEvent>()
+            .add_fixed_event::<DieEvent>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(spawn_player)
+                    .with_system(spawn_camera),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing).with_system(clean_animation_effects),
+            )
+            .add_fixed_system_set(
+                SystemSet::new()
+                    .with_system(move_player)
+                    .with_system(hurt_player.after(UpdateSpatialHash))
+                    .with_system(deal_damage.label(ApplyDamage))
+                    .with_system(shake_camera_on_hurt)
+                    .with_system(tick_invincibility_frames)
+                    .with_system(handle_die)
+                    .with_system(spawn_corpses)
+                    .with_system(clean_corpses)
+                    .with_system(gather_xp_gems.after(UpdateSpatialHash))
+                    .with_system(merge_xp_gems.after(gather_xp_gems)),
+            )
+            // the camera follows the interpolated player
+            .add_system_set_to_stage(
+                CoreStage::PostUpdate,
+                SystemSet::new().with_run_criteria(playing).with_system(
+                    move_camera
+                        .after(Interpolate)
+                        .before(TransformSystem::TransformPropagate),
+                ),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing)
+                    .with_system(despawn_with::<Player>)
+                    .with_system(despawn_with::<MainCamera>)
+                    .with_system(despawn_with::<Item>)
+                    .with_system(despawn_with::<VFX>),
+            );
     }
 }
 
@@ -101,7 +255,6 @@ fn spawn_camera(mut commands: Commands) {
 fn spawn_player(
     mut commands: Commands,
     textures: Res<TextureAssets>,
-    fonts: Res<FontAssets>,
     config: Res<GameConfiguration>,
 ) {
     /*
@@ -114,18 +267,7 @@ fn spawn_player(
     let handle = texture_atlases.add(atlas);
     */
 
-    let font = fonts.fira_sans.clone();
-    let style = TextStyle {
-        font,
-        font_size: 10.,
-        color: Color::WHITE,
-    };
-    let alignment = TextAlignment {
-        vertical: VerticalAlign::Center,
-        horizontal: HorizontalAlign::Center,
-    };
-
-    commands
+    let player = commands
         .spawn_bundle(SpriteSheetBundle {
             texture_atlas: textures.castle.clone(),
             transform: Transform::from_translation(Vec3::new(0., 0., sprite_z(Vec2::ZERO)))
@@ -134,49 +276,131 @@ fn spawn_player(
             ..Default::default()
         })
         .insert(Player)
+        .insert(CharacterAnimator::new("player"))
+        .insert(Interpolated::default())
         .insert(BaseMoveSpeed(120.))
         .insert(HurtBox {
             pos: Vec2::ZERO,
             size: Vec2::splat(16. * config.scale * 0.6),
         })
         .insert(Health(100.))
-        .insert(AttackNearest {
-            damage: 1.,
-            interval: Timer::from_seconds(2., true),
+        .insert(MaxHealth(100.))
+        .insert(HealthDisplay {
+            number: true,
+            ..Default::default()
         })
-        .with_children(|parent| {
-            parent.spawn_bundle(Text2dBundle {
-                text: Text::with_section(&format!("{}", 100.), style, alignment),
-                transform: Transform::from_translation(Vec3::new(0., -20., 10.)),
-                ..Default::default()
-            });
-        });
+        .insert(PickupRadius(150.))
+        .insert(Experience::default())
+        .insert(Level::default())
+        .insert(Facing(Vec2::X))
+        .insert(Aim::default())
+        .insert(WeaponModifiers::default())
+        .id();
+
+    commands
+        .spawn()
+        .insert(Weapon::new(WeaponKind::Bolt, player));
 }
 
 fn gather_xp_gems(
-    time: Res<Time>,
-    player: Query<&Transform, With<Player>>,
-    mut items: Query<(Entity, &mut Transform), (With<XpGem>, With<Item>, Without<Player>)>,
+    time: Res<SimTime>,
+    gem_hash: Res<SpatialHash<XpGem>>,
+    player: Query<(Entity, &Transform, &PickupRadius), With<Player>>,
+    mut gems: Query<(&XpGem, &mut Transform), (With<Item>, Without<Player>)>,
     mut commands: Commands,
+    mut event_xp: EventWriter<XpGainedEvent>,
+    mut event_sfx: EventWriter<PlaySfx>,
 ) {
     let dt = time.delta_seconds();
-    let player = player.single().translation;
+    let (player_entity, player, pickup_radius) = player.single();
+    let player = player.translation.xy();
+
+    // the gems out of the pickup radius don't move
+    for entry in gem_hash.within_radius(player, pickup_radius.0) {
+        let (gem, mut transform) = match gems.get_mut(entry.entity) {
+            Ok(gem) => gem,
+            Err(_) => continue,
+        };
+        let p = transform.translation.xy();
+
+        if (player - p).length() < GATHER_RADIUS {
+            event_xp.send(XpGainedEvent {
+                entity: player_entity,
+                amount: gem.value,
+            });
+            event_sfx.send(PlaySfx(Sfx::XpPickup));
+            release::<XpGem>(&mut commands, entry.entity);
+            continue;
+        }
 
-    for (e, mut item) in items.iter_mut() {
-        let p = &mut item.translation;
+        let dir = (player - p).clamp_length_max(1.);
+        let new_p = p + dir * dt * GEM_SPEED;
+        transform.translation = new_p.extend(sprite_z(new_p));
+    }
+}
 
-        if (player.xy() - p.xy()).length() < 20. {
-            println!("Picking up XP!");
-            commands.entity(e).despawn_recursive();
+/// Once there are too many gems on the ground, the gems close to each other are merged into one
+/// The gems within the pickup radius are left alone, they are being gathered
+#[allow(clippy::type_complexity)]
+fn merge_xp_gems(
+    mut commands: Commands,
+    gem_hash: Res<SpatialHash<XpGem>>,
+    player: Query<(&Transform, &PickupRadius), With<Player>>,
+    mut gems: Query<
+        (Entity, &Transform, &mut XpGem, &mut TextureAtlasSprite),
+        (With<Item>, Without<Player>),
+    >,
+) {
+    if gem_hash.len() <= MAX_GEMS {
+        return;
+    }
+    let (player, pickup_radius) = player.single();
+    let player = player.translation.xy();
+    let gathered = |p: Vec2| (p - player).length() <= pickup_radius.0;
+
+    let mut merged = HashSet::new();
+    let mut merges = Vec::new();
+    // in query order rather than the order of the cells, so that replays merge the same gems
+    for (e, transform, _, _) in gems.iter() {
+        let p = transform.translation.xy();
+        if merged.contains(&e) || gathered(p) {
             continue;
         }
+        // the hash was built before `gather_xp_gems` moved the gems of the pickup radius,
+        // so the candidates are checked again at their current position
+        let absorbed: Vec<Entity> = gem_hash
+            .within_radius(p, MERGE_RADIUS)
+            .filter(|other| other.entity != e && !merged.contains(&other.entity))
+            .filter_map(|other| {
+                let (other, transform, _, _) = gems.get(other.entity).ok()?;
+                let q = transform.translation.xy();
+                if (q - p).length() <= MERGE_RADIUS && !gathered(q) {
+                    Some(other)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        if absorbed.is_empty() {
+            continue;
+        }
+        merged.insert(e);
+        merged.extend(absorbed.iter().copied());
+        merges.push((e, absorbed));
+    }
 
-        if (player.xy() - p.xy()).length() < 150. {
-            let dir = (player.xy() - p.xy()).clamp_length_max(1.);
-            let SPEED = 500.;
-            let new_p = p.xy() + dir * dt * SPEED;
-
-            *p = Vec3::new(new_p.x, new_p.y, sprite_z(new_p));
+    for (e, absorbed) in merges {
+        let value: u32 = absorbed
+            .iter()
+            .filter_map(|absorbed| gems.get(*absorbed).ok())
+            .map(|(_, _, gem, _)| gem.value)
+            .sum();
+        for absorbed in absorbed {
+            release::<XpGem>(&mut commands, absorbed);
+        }
+        if let Ok((_, _, mut gem, mut sprite)) = gems.get_mut(e) {
+            gem.value += value;
+            sprite.index = gem.tier().sprite_index();
         }
     }
 }
@@ -186,19 +410,31 @@ fn clean_animation_effects(
     animations: Query<(Entity, &Handle<SpriteSheetAnimation>), (Without<Play>, With<VFX>)>,
 ) {
     for (e, _animation) in animations.iter() {
-        commands.entity(e).despawn_recursive();
+        release::<VFX>(&mut commands, e);
     }
 }
 
 fn deal_damage(
     mut event_deal_damage: EventReader<DealDamageEvent>,
     mut event_die: EventWriter<DieEvent>,
-    mut q: Query<&mut Health>,
+    mut event_sfx: EventWriter<PlaySfx>,
+    mut q: Query<(&mut Health, Option<&Player>)>,
 ) {
     for event in event_deal_damage.iter() {
-        let mut health = q.get_mut(event.entity).unwrap();
-        dbg!(&health.0);
-        health.0 -= 1.;
+        let (mut health, player) = match q.get_mut(event.entity) {
+            Ok(target) => target,
+            Err(_) => continue,
+        };
+        if health.0 <= 0. {
+            // already dying, don't send a second DieEvent
+            continue;
+        }
+        health.0 -= event.amount;
+        event_sfx.send(PlaySfx(if player.is_some() {
+            Sfx::PlayerHurt
+        } else {
+            Sfx::Hit
+        }));
 
         if health.0 <= 0. {
             event_die.send(DieEvent {
@@ -208,72 +444,78 @@ fn deal_damage(
     }
 }
 
-fn clean_corpses(mut commands: Commands, time: Res<Time>, mut q: Query<(Entity, &mut Corpse)>) {
+fn clean_corpses(mut commands: Commands, time: Res<SimTime>, mut q: Query<(Entity, &mut Corpse)>) {
     for (e, mut corpse) in q.iter_mut() {
         corpse.timer.tick(time.delta());
 
         if corpse.timer.just_finished() {
-            commands.entity(e).despawn_recursive();
+            release::<Enemy>(&mut commands, e);
         }
     }
 }
 
 fn spawn_corpses(
     mut commands: Commands,
-    //textures: Res<TextureAssets>,
+    data: Res<DataAssets>,
+    archetypes: Res<Assets<EnemyArchetypes>>,
     mut q: Query<
-        (Entity, &Transform, &mut TextureAtlasSprite),
+        (
+            Entity,
+            &Archetype,
+            &mut TextureAtlasSprite,
+            &CharacterAnimator,
+        ),
         (With<Enemy>, With<Dead>, Without<Play>),
     >,
 ) {
-    for (e, transform, mut sprite) in q.iter_mut() {
+    let archetypes = archetypes.get(&data.enemies);
+
+    for (e, kind, mut sprite, animator) in q.iter_mut() {
+        // the death animation didn't start yet
+        if animator.state() != Some(CharacterState::Death) {
+            continue;
+        }
         commands.entity(e).remove::<Dead>().insert(Corpse {
             timer: Timer::from_seconds(5., false),
         });
-        sprite.index = 43;
+        if let Some(archetype) = archetypes.and_then(|archetypes| archetypes.0.get(&kind.0)) {
+            sprite.index = archetype.corpse_frame;
+        }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_die(
     mut event_die: EventReader<DieEvent>,
-    q: Query<(Entity, &Transform)>,
+    q: Query<(Entity, &Transform, &Archetype), With<Enemy>>,
     mut commands: Commands,
-    mut animations: ResMut<Assets<SpriteSheetAnimation>>,
     textures: Res<TextureAssets>,
+    data: Res<DataAssets>,
+    archetypes: Res<Assets<EnemyArchetypes>>,
     config: Res<GameConfiguration>,
+    mut gem_pool: ResMut<Pool<XpGem>>,
+    mut event_sfx: EventWriter<PlaySfx>,
 ) {
-    let animation_handle = animations.add(
-        SpriteSheetAnimation::from_range(
-            40..=43,                             // Indices of the sprite atlas
-            Duration::from_secs_f64(1.0 / 10.0), // Duration of each frame
-        )
-        .once(),
-    );
+    let archetypes = archetypes.get(&data.enemies);
 
     for event in event_die.iter() {
-        let entity = event.entity;
-        let (entity, transform) = q.get(entity).unwrap();
+        let (entity, transform, kind) = match q.get(event.entity) {
+            Ok(enemy) => enemy,
+            Err(_) => continue,
+        };
+        let archetype = archetypes.and_then(|archetypes| archetypes.0.get(&kind.0));
         let p = transform.translation;
 
-        commands
-            .entity(entity)
-            .remove::<Alive>()
-            .insert(Dead)
-            .insert_bundle(SpriteSheetBundle {
-                texture_atlas: textures.necromancer.clone(),
-                transform: Transform::from_translation(Vec3::new(
-                    p.x,
-                    p.y,
-                    sprite_z(Vec2::new(p.x, p.y)),
-                ))
-                .with_scale(Vec3::splat(config.scale)),
-                ..Default::default()
-            })
-            .insert(animation_handle.clone())
-            .insert(Play);
+        // the `CharacterAnimator` plays the death animation, then the enemy becomes a corpse
+        commands.entity(entity).remove::<Alive>().insert(Dead);
+        event_sfx.send(PlaySfx(Sfx::EnemyDeath));
 
-        commands
-            .spawn_bundle(SpriteSheetBundle {
+        let gem = XpGem {
+            value: archetype.map_or(1, |archetype| archetype.xp),
+        };
+        gem_pool
+            .spawn(&mut commands)
+            .insert_bundle(SpriteSheetBundle {
                 texture_atlas: textures.misc.clone(),
                 transform: Transform::from_translation(Vec3::new(
                     p.x,
@@ -282,79 +524,20 @@ fn handle_die(
                 ))
                 .with_scale(Vec3::splat(config.scale / 2.)),
                 sprite: TextureAtlasSprite {
-                    index: 20,
+                    index: gem.tier().sprite_index(),
                     ..Default::default()
                 },
                 ..Default::default()
             })
             .insert(Item)
-            .insert(XpGem { value: 1 });
-    }
-}
-
-fn trigger_attack_nearest(
-    mut commands: Commands,
-    player: Query<&Transform, With<Player>>,
-    enemies: Query<(Entity, &Transform), (With<Enemy>, With<Alive>)>,
-    mut attacks: Query<&mut AttackNearest>,
-    time: Res<Time>,
-    mut event_deal_damage: EventWriter<DealDamageEvent>,
-    mut animations: ResMut<Assets<SpriteSheetAnimation>>,
-    textures: Res<TextureAssets>,
-    config: Res<GameConfiguration>,
-) {
-    let player = player.single();
-    let mut n_attacks = 0;
-    for mut attack in attacks.iter_mut() {
-        attack.interval.tick(time.delta());
-        if attack.interval.just_finished() {
-            n_attacks += 1;
-        }
-    }
-    let mut enemies_distance: Vec<_> = enemies
-        .iter()
-        .map(|(e, transform)| {
-            (
-                e,
-                (player.translation.xy() - transform.translation.xy()).length(),
-                transform,
-            )
-        })
-        .collect();
-    enemies_distance.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-    let targets = enemies_distance.iter().take(n_attacks);
-
-    let animation_handle = animations.add(
-        SpriteSheetAnimation::from_range(
-            136..=139,                           // Indices of the sprite atlas
-            Duration::from_secs_f64(1.0 / 10.0), // Duration of each frame
-        )
-        .once(),
-    );
-    for &(entity, _distance, transform) in targets {
-        let p = transform.translation;
-        commands
-            .spawn_bundle(SpriteSheetBundle {
-                texture_atlas: textures.magic.clone(),
-                transform: Transform::from_translation(Vec3::new(
-                    p.x,
-                    p.y,
-                    sprite_z(Vec2::new(p.x, p.y)) + 0.1,
-                ))
-                .with_scale(Vec3::splat(config.scale)),
-                //sprite: TextureAtlasSprite::new(27),
-                ..Default::default()
-            })
-            .insert(animation_handle.clone())
-            .insert(Play)
-            .insert(VFX);
-        event_deal_damage.send(DealDamageEvent { entity });
+            .insert(Interpolated::default())
+            .insert(gem);
     }
 }
 
 fn tick_invincibility_frames(
     mut commands: Commands,
-    time: Res<Time>,
+    time: Res<SimTime>,
     mut q: Query<(Entity, &mut InvincibilityFrames, &mut TextureAtlasSprite)>,
 ) {
     for (e, mut frame, mut sprite) in q.iter_mut() {
@@ -368,8 +551,7 @@ fn tick_invincibility_frames(
 
 fn hurt_player(
     mut commands: Commands,
-    mut animations: ResMut<Assets<SpriteSheetAnimation>>,
-    textures: Res<TextureAssets>,
+    mut event_deal_damage: EventWriter<DealDamageEvent>,
     mut player: Query<
         (
             Entity,
@@ -380,7 +562,8 @@ fn hurt_player(
         ),
         (With<Player>, Without<Enemy>),
     >,
-    enemies: Query<(Entity, &Transform, &HitBox), (With<Enemy>, Without<Player>)>,
+    enemies: Query<(&Transform, &HitBox), (With<Enemy>, With<Alive>, Without<Player>)>,
+    enemy_hash: Res<SpatialHash<Enemy>>,
     config: Res<GameConfiguration>,
 ) {
     let (player_entity, player_pos, player_hurt_box, mut sprite, invincibility) =
@@ -389,19 +572,30 @@ fn hurt_player(
         return;
     }
 
+    let player_center = player_pos.translation.xy() + player_hurt_box.pos;
     let mut hit = false;
-    for (enemy_entity, enemy_transform, enemy_hit_box) in enemies.iter() {
+    // enemy hit boxes are the size of their hurt boxes, so the index finds every candidate
+    for entry in enemy_hash.overlapping(player_center, player_hurt_box.size) {
+        let (enemy_transform, enemy_hit_box) = match enemies.get(entry.entity) {
+            Ok(enemy) => enemy,
+            Err(_) => continue,
+        };
         if let Some(_collision) = collide(
             enemy_transform.translation + enemy_hit_box.pos.extend(0.),
             enemy_hit_box.size,
-            (player_pos.translation.xy() + player_hurt_box.pos).extend(0.),
+            player_center.extend(0.),
             player_hurt_box.size,
         ) {
             hit = true;
-            dbg!("hit from ", enemy_entity);
-            commands.entity(player_entity).insert(InvincibilityFrames {
-                timer: Timer::new(Duration::from_secs_f32(0.2), false),
+            event_deal_damage.send(DealDamageEvent {
+                entity: player_entity,
+                source: entry.entity,
+                amount: enemy_hit_box.damage,
+                kind: DamageKind::Physical,
             });
+            commands
+                .entity(player_entity)
+                .insert(InvincibilityFrames::from_seconds(0.2));
 
             break;
         }
@@ -413,37 +607,80 @@ fn hurt_player(
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn move_player(
-    time: Res<Time>,
+    time: Res<SimTime>,
     actions: Res<Actions>,
     mut player_query: Query<
-        (&mut Transform, &BaseMoveSpeed, &mut TextureAtlasSprite),
+        (
+            &mut Transform,
+            &BaseMoveSpeed,
+            &mut TextureAtlasSprite,
+            &mut Facing,
+            &mut Aim,
+            &mut CharacterAnimator,
+        ),
         With<Player>,
     >,
 ) {
+    let (mut player_transform, base_speed, mut sprite, mut facing, mut aim, mut animator) =
+        player_query.single_mut();
+    aim.0 = actions.aim_direction;
+    animator.moving = actions
+        .player_movement
+        .map_or(false, |movement| movement != Vec2::ZERO);
+    // the player looks where they aim, or else where they walk
+    if let Some(aim) = aim.0 {
+        sprite.flip_x = aim.x < 0.;
+    }
+
     if actions.player_movement.is_none() {
         return;
     }
 
-    let (mut player_transform, base_speed, mut sprite) = player_query.single_mut();
+    if actions.player_movement.unwrap() != Vec2::ZERO {
+        facing.0 = actions.player_movement.unwrap().normalize();
+    }
 
     let movement =
         actions.player_movement.unwrap().extend(0.) * base_speed.0 * time.delta_seconds();
-    if movement.x <= 0. {
-        sprite.flip_x = true;
-    } else {
-        sprite.flip_x = false;
+    if aim.0.is_none() {
+        sprite.flip_x = movement.x <= 0.;
     }
     player_transform.translation += movement;
     player_transform.translation.z = sprite_z(player_transform.translation.xy());
 }
 
+fn shake_camera_on_hurt(
+    mut shake: ResMut<CameraShake>,
+    mut event_deal_damage: EventReader<DealDamageEvent>,
+    player: Query<(), With<Player>>,
+) {
+    for event in event_deal_damage.iter() {
+        if player.get(event.entity).is_ok() {
+            shake.trauma = (shake.trauma + SHAKE_TRAUMA_PER_HIT).min(1.);
+        }
+    }
+}
+
 fn move_camera(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut shake: ResMut<CameraShake>,
     mut camera: Query<&mut Transform, (With<Camera>, With<MainCamera>)>,
     player: Query<&Transform, (With<Player>, Without<Camera>)>,
 ) {
     let mut camera = camera.single_mut();
     let player = player.single();
 
-    camera.translation = player.translation.xy().extend(camera.translation.z);
+    // cosmetic only, so it doesn't draw from the gameplay rng
+    let mut offset = Vec2::ZERO;
+    if settings.screen_shake && shake.trauma > 0. {
+        let mut rng = rand::thread_rng();
+        let amplitude = SHAKE_MAX_OFFSET * shake.trauma * shake.trauma;
+        offset = Vec2::new(rng.gen_range(-1.0..1.), rng.gen_range(-1.0..1.)) * amplitude;
+    }
+    shake.trauma = (shake.trauma - SHAKE_DECAY * time.delta_seconds()).max(0.);
+
+    camera.translation = (player.translation.xy() + offset).extend(camera.translation.z);
 }
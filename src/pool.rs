@@ -0,0 +1,174 @@
+use std::marker::PhantomData;
+
+use crate::utils::despawn_with;
+use crate::GameState;
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics};
+use bevy::ecs::system::{Command, EntityCommands};
+use bevy::ecs::world::EntityMut;
+use bevy::prelude::*;
+use bevy::transform::hierarchy::despawn_with_children_recursive;
+
+/// Kind of entity recycled by a `Pool`, identified by its marker component
+pub trait Poolable: Component {
+    /// Name of the pool in the diagnostics
+    const NAME: &'static str;
+    /// Diagnostics of the number of entities waiting in the pool and in use
+    const FREE_DIAGNOSTIC: DiagnosticId;
+    const ACTIVE_DIAGNOSTIC: DiagnosticId;
+
+    /// Removes the components of the kind when the entity goes back to the pool
+    /// They are inserted again when it is reused, so every reuse starts fresh
+    fn reset(entity: &mut EntityMut);
+}
+
+/// Hidden entities of the kind `T`, waiting to be reused
+pub struct Pool<T> {
+    free: Vec<Entity>,
+    active: usize,
+    spawned: usize,
+    reused: usize,
+    marker: PhantomData<T>,
+}
+
+impl<T> Default for Pool<T> {
+    fn default() -> Self {
+        Pool {
+            free: Vec::new(),
+            active: 0,
+            spawned: 0,
+            reused: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Poolable> Pool<T> {
+    /// A hidden entity of the pool, or a new one if the pool is empty
+    /// The caller inserts its bundle and components as for a new entity
+    pub fn spawn<'w, 's, 'a>(
+        &mut self,
+        commands: &'a mut Commands<'w, 's>,
+    ) -> EntityCommands<'w, 's, 'a> {
+        self.active += 1;
+        match self.free.pop() {
+            Some(e) => {
+                self.reused += 1;
+                let mut entity = commands.entity(e);
+                entity.remove::<Pooled<T>>();
+                entity
+            }
+            None => {
+                self.spawned += 1;
+                commands.spawn()
+            }
+        }
+    }
+
+    /// Entities waiting in the pool
+    pub fn free(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Entities taken from the pool and not released yet
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// Entities created because the pool was empty
+    pub fn spawned(&self) -> usize {
+        self.spawned
+    }
+
+    /// Entities taken from the pool instead of spawned
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+}
+
+/// Marks the hidden entities of a `Pool<T>`
+#[derive(Component)]
+pub struct Pooled<T: Poolable>(PhantomData<T>);
+
+/// Sends the entity back to its pool instead of despawning it:
+/// its children are despawned, it is reset by `Poolable::reset` and hidden
+pub fn release<T: Poolable>(commands: &mut Commands, entity: Entity) {
+    commands.add(Release::<T> {
+        entity,
+        marker: PhantomData,
+    });
+}
+
+struct Release<T> {
+    entity: Entity,
+    marker: PhantomData<T>,
+}
+
+impl<T: Poolable> Command for Release<T> {
+    fn write(self, world: &mut World) {
+        let children = match world.get_entity(self.entity) {
+            Some(entity) if entity.contains::<T>() => entity
+                .get::<Children>()
+                .map(|children| children.iter().copied().collect::<Vec<_>>()),
+            // already released or despawned
+            _ => return,
+        };
+        for child in children.into_iter().flatten() {
+            despawn_with_children_recursive(world, child);
+        }
+
+        let mut entity = world.entity_mut(self.entity);
+        T::reset(&mut entity);
+        entity
+            .remove::<Children>()
+            .insert(Visibility { is_visible: false })
+            .insert(Pooled::<T>(PhantomData));
+
+        if let Some(mut pool) = world.get_resource_mut::<Pool<T>>() {
+            pool.active = pool.active.saturating_sub(1);
+            pool.free.push(self.entity);
+        }
+    }
+}
+
+/// This plugin adds the `Pool<T>` and reports its size in the diagnostics
+/// The pool is emptied at the end of every run
+pub struct PoolPlugin<T>(PhantomData<T>);
+
+impl<T> Default for PoolPlugin<T> {
+    fn default() -> Self {
+        PoolPlugin(PhantomData)
+    }
+}
+
+impl<T: Poolable> Plugin for PoolPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Pool<T>>()
+            .add_startup_system(register_pool_diagnostics::<T>)
+            .add_system(measure_pool::<T>)
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing)
+                    .with_system(despawn_with::<Pooled<T>>)
+                    .with_system(reset_pool::<T>),
+            );
+    }
+}
+
+fn register_pool_diagnostics<T: Poolable>(diagnostics: Option<ResMut<Diagnostics>>) {
+    if let Some(mut diagnostics) = diagnostics {
+        let free = format!("pool {} free", T::NAME);
+        let active = format!("pool {} active", T::NAME);
+        diagnostics.add(Diagnostic::new(T::FREE_DIAGNOSTIC, &free, 20));
+        diagnostics.add(Diagnostic::new(T::ACTIVE_DIAGNOSTIC, &active, 20));
+    }
+}
+
+fn measure_pool<T: Poolable>(pool: Res<Pool<T>>, diagnostics: Option<ResMut<Diagnostics>>) {
+    if let Some(mut diagnostics) = diagnostics {
+        diagnostics.add_measurement(T::FREE_DIAGNOSTIC, pool.free() as f64);
+        diagnostics.add_measurement(T::ACTIVE_DIAGNOSTIC, pool.active() as f64);
+    }
+}
+
+fn reset_pool<T: Poolable>(mut pool: ResMut<Pool<T>>) {
+    *pool = Pool::default();
+}
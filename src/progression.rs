@@ -0,0 +1,127 @@
+use crate::player::{ApplyDamage, Health, Player};
+use crate::sfx::{PlaySfx, Sfx};
+use crate::timestep::FixedTimestepAppExt;
+use crate::GameState;
+use bevy::prelude::*;
+
+pub struct ProgressionPlugin;
+
+/// Experience gathered since the last level-up
+#[derive(Component, Default)]
+pub struct Experience(pub u32);
+
+#[derive(Component)]
+pub struct Level(pub u32);
+
+impl Default for Level {
+    fn default() -> Self {
+        Level(1)
+    }
+}
+
+/// XP needed to go from `level` to `level + 1` is `base + growth * (level - 1)^exponent`
+pub struct XpCurve {
+    pub base: f32,
+    pub growth: f32,
+    pub exponent: f32,
+}
+
+impl Default for XpCurve {
+    fn default() -> Self {
+        XpCurve {
+            base: 5.,
+            growth: 10.,
+            exponent: 1.3,
+        }
+    }
+}
+
+impl XpCurve {
+    pub fn xp_to_next(&self, level: u32) -> u32 {
+        let steps = level.saturating_sub(1) as f32;
+        // at least 1, or gaining XP would level up forever
+        ((self.base + self.growth * steps.powf(self.exponent)).round() as u32).max(1)
+    }
+}
+
+/// Level-ups that were earned but not handled yet by the `GameState::LevelUp` screen
+#[derive(Default)]
+pub struct PendingLevelUps(pub u32);
+
+pub struct XpGainedEvent {
+    pub entity: Entity,
+    pub amount: u32,
+}
+
+pub struct LevelUpEvent {
+    pub entity: Entity,
+    pub level: u32,
+}
+
+/// This plugin turns gathered XP into levels
+/// Every level-up pauses `GameState::Playing` by pushing `GameState::LevelUp` on top of it,
+/// the upgrade choice itself is handled by the `UpgradePlugin`
+impl Plugin for ProgressionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<XpCurve>()
+            .init_resource::<PendingLevelUps>()
+            .add_fixed_event::<XpGainedEvent>()
+            .add_fixed_event::<LevelUpEvent>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(reset_pending_level_ups),
+            )
+            .add_fixed_system_set(
+                SystemSet::new()
+                    .with_system(gain_xp)
+                    .with_system(start_level_up.after(ApplyDamage)),
+            );
+    }
+}
+
+fn reset_pending_level_ups(mut pending: ResMut<PendingLevelUps>) {
+    pending.0 = 0;
+}
+
+fn gain_xp(
+    curve: Res<XpCurve>,
+    mut event_xp: EventReader<XpGainedEvent>,
+    mut event_level_up: EventWriter<LevelUpEvent>,
+    mut event_sfx: EventWriter<PlaySfx>,
+    mut q: Query<(&mut Experience, &mut Level)>,
+) {
+    for event in event_xp.iter() {
+        if let Ok((mut experience, mut level)) = q.get_mut(event.entity) {
+            experience.0 += event.amount;
+
+            while experience.0 >= curve.xp_to_next(level.0) {
+                experience.0 -= curve.xp_to_next(level.0);
+                level.0 += 1;
+                event_level_up.send(LevelUpEvent {
+                    entity: event.entity,
+                    level: level.0,
+                });
+                event_sfx.send(PlaySfx(Sfx::LevelUp));
+            }
+        }
+    }
+}
+
+/// Pushes `GameState::LevelUp` while there are pending level-ups, checked every tick
+fn start_level_up(
+    mut event_level_up: EventReader<LevelUpEvent>,
+    mut pending: ResMut<PendingLevelUps>,
+    mut state: ResMut<State<GameState>>,
+    player: Query<&Health, With<Player>>,
+) {
+    pending.0 += event_level_up.iter().count() as u32;
+    if pending.0 == 0 || state.current() != &GameState::Playing {
+        return;
+    }
+    // the run is over, the game over screen comes next
+    if player.iter().any(|health| health.0 <= 0.) {
+        return;
+    }
+
+    // fails if another transition is already queued this frame, it is retried on the next tick
+    let _ = state.push(GameState::LevelUp);
+}
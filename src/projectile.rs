@@ -0,0 +1,145 @@
+use crate::collide_aabb::collide;
+use crate::enemy::{sprite_z, Enemy};
+use crate::player::{DamageKind, DealDamageEvent, HitBox, HurtBox, InvincibilityFrames, Player};
+use crate::spatial::{SpatialHash, UpdateSpatialHash};
+use crate::timestep::{FixedTimestepAppExt, SimTime};
+use crate::utils::despawn_with;
+use crate::GameState;
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+
+pub struct ProjectilePlugin;
+
+/// Which `HurtBox`es a projectile can hit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProjectileTarget {
+    Enemies,
+    Player,
+}
+
+/// A travelling attack, its size and damage are given by the `HitBox` of the same entity
+#[derive(Component)]
+pub struct Projectile {
+    pub source: Entity,
+    pub velocity: Vec2,
+    pub lifetime: Timer,
+    /// Number of targets the projectile goes through before being despawned
+    pub pierce: u32,
+    pub kind: DamageKind,
+    pub target: ProjectileTarget,
+    /// Targets already hit, a projectile never hits the same target twice
+    hit: Vec<Entity>,
+}
+
+impl Projectile {
+    pub fn new(
+        source: Entity,
+        velocity: Vec2,
+        lifetime: f32,
+        pierce: u32,
+        kind: DamageKind,
+        target: ProjectileTarget,
+    ) -> Self {
+        Projectile {
+            source,
+            velocity,
+            lifetime: Timer::from_seconds(lifetime, false),
+            pierce,
+            kind,
+            target,
+            hit: Vec::new(),
+        }
+    }
+}
+
+/// This plugin moves projectiles and applies their damage on contact
+/// Projectile logic is only active during the State `GameState::Playing`
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_fixed_system_set(
+            SystemSet::new()
+                .with_system(move_projectiles)
+                .with_system(projectile_hits.after(UpdateSpatialHash)),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Playing).with_system(despawn_with::<Projectile>),
+        );
+    }
+}
+
+fn move_projectiles(
+    mut commands: Commands,
+    time: Res<SimTime>,
+    mut projectiles: Query<(Entity, &mut Transform, &mut Projectile)>,
+) {
+    for (e, mut transform, mut projectile) in projectiles.iter_mut() {
+        projectile.lifetime.tick(time.delta());
+        if projectile.lifetime.finished() {
+            commands.entity(e).despawn_recursive();
+            continue;
+        }
+
+        let p = transform.translation.xy() + projectile.velocity * time.delta_seconds();
+        transform.translation = p.extend(sprite_z(p) + 0.1);
+    }
+}
+
+fn projectile_hits(
+    mut commands: Commands,
+    mut projectiles: Query<(Entity, &Transform, &HitBox, &mut Projectile)>,
+    enemy_hash: Res<SpatialHash<Enemy>>,
+    player: Query<(Entity, &Transform, &HurtBox, Option<&InvincibilityFrames>), With<Player>>,
+    mut event_deal_damage: EventWriter<DealDamageEvent>,
+) {
+    for (e, transform, hit_box, mut projectile) in projectiles.iter_mut() {
+        let pos = transform.translation + hit_box.pos.extend(0.);
+        let hits = |target: &Transform, hurt_box: &HurtBox| {
+            collide(
+                pos,
+                hit_box.size,
+                (target.translation.xy() + hurt_box.pos).extend(0.),
+                hurt_box.size,
+            )
+            .is_some()
+        };
+
+        let mut targets = Vec::new();
+        match projectile.target {
+            ProjectileTarget::Enemies => {
+                for target in enemy_hash.overlapping(pos.xy(), hit_box.size) {
+                    if !projectile.hit.contains(&target.entity) {
+                        targets.push(target.entity);
+                    }
+                }
+            }
+            ProjectileTarget::Player => {
+                for (entity, target, hurt_box, invincibility) in player.iter() {
+                    if invincibility.is_none()
+                        && !projectile.hit.contains(&entity)
+                        && hits(target, hurt_box)
+                    {
+                        targets.push(entity);
+                        commands
+                            .entity(entity)
+                            .insert(InvincibilityFrames::from_seconds(0.2));
+                    }
+                }
+            }
+        }
+
+        for entity in targets {
+            event_deal_damage.send(DealDamageEvent {
+                entity,
+                source: projectile.source,
+                amount: hit_box.damage,
+                kind: projectile.kind,
+            });
+            projectile.hit.push(entity);
+
+            if projectile.hit.len() as u32 > projectile.pierce {
+                commands.entity(e).despawn_recursive();
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::actions::Actions;
+use crate::rng::{GameRng, RngSeed};
+use crate::timestep::{FixedStage, FixedTimestepAppExt, FixedUpdateStage};
+use crate::GameState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct ReplayPlugin;
+
+/// Replay files given on the command line with `--record <path>` and `--replay <path>`
+#[derive(Default)]
+pub struct ReplaySettings {
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+}
+
+impl ReplaySettings {
+    pub fn from_args() -> Self {
+        let arg = |name: &str| {
+            std::env::args()
+                .skip_while(|arg| arg != name)
+                .nth(1)
+                .map(PathBuf::from)
+        };
+
+        ReplaySettings {
+            record: arg("--record"),
+            replay: arg("--replay"),
+        }
+    }
+}
+
+/// Everything needed to reproduce a run: its seed and the player input of every tick
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct Replay {
+    /// Version of the game that recorded the run, other versions may not reproduce it
+    pub version: String,
+    pub seed: u64,
+    /// Movement of every tick, run-length encoded
+    pub movement: Vec<MovementRun>,
+    /// Index of the upgrade picked at each level-up
+    pub upgrades: Vec<usize>,
+}
+
+/// `movement` and `aim` are held for `ticks` ticks in a row
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MovementRun {
+    pub ticks: u32,
+    pub movement: Option<(f32, f32)>,
+    #[serde(default)]
+    pub aim: Option<(f32, f32)>,
+}
+
+impl Replay {
+    /// An empty replay of the current version
+    pub fn new(seed: u64) -> Self {
+        Replay {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            seed,
+            ..Default::default()
+        }
+    }
+
+    /// Appends the actions of one tick
+    pub fn push(&mut self, actions: &Actions) {
+        let movement = actions.player_movement.map(|m| (m.x, m.y));
+        let aim = actions.aim_direction.map(|a| (a.x, a.y));
+        match self.movement.last_mut() {
+            Some(run) if run.movement == movement && run.aim == aim => run.ticks += 1,
+            _ => self.movement.push(MovementRun {
+                ticks: 1,
+                movement,
+                aim,
+            }),
+        }
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.movement.iter().map(|run| run.ticks as u64).sum()
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = fs::read_to_string(path)?;
+        Ok(ron::de::from_str(&file)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, ron::ser::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// Run being recorded, written to `path` when the run ends
+pub struct ReplayRecorder {
+    pub path: PathBuf,
+    pub replay: Replay,
+}
+
+/// Replay fed to the game instead of the player input
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    run: usize,
+    tick_in_run: u32,
+    upgrade: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayer {
+            replay,
+            run: 0,
+            tick_in_run: 0,
+            upgrade: 0,
+        }
+    }
+
+    fn rewind(&mut self) {
+        self.run = 0;
+        self.tick_in_run = 0;
+        self.upgrade = 0;
+    }
+
+    pub fn finished(&self) -> bool {
+        self.run >= self.replay.movement.len()
+    }
+
+    /// Actions of the next tick, no movement once the replay is over
+    pub fn next_actions(&mut self) -> Actions {
+        let run = match self.replay.movement.get(self.run) {
+            Some(run) => *run,
+            None => return Actions::default(),
+        };
+        self.tick_in_run += 1;
+        if self.tick_in_run >= run.ticks {
+            self.run += 1;
+            self.tick_in_run = 0;
+        }
+
+        Actions {
+            player_movement: run.movement.map(|(x, y)| Vec2::new(x, y)),
+            aim_direction: run.aim.map(|(x, y)| Vec2::new(x, y)),
+        }
+    }
+
+    /// Upgrade picked at the next level-up, `None` once the recorded upgrades run out
+    pub fn next_upgrade(&mut self) -> Option<usize> {
+        let upgrade = self.replay.upgrades.get(self.upgrade).copied();
+        if upgrade.is_some() {
+            self.upgrade += 1;
+        } else if self.upgrade == self.replay.upgrades.len() {
+            warn!("The replay has no upgrade left, the player picks it");
+            // only warned once
+            self.upgrade += 1;
+        }
+        upgrade
+    }
+}
+
+/// This plugin records runs with `--record <path>` and plays them back with `--replay <path>`
+/// While replaying, the recorded actions replace the player input at every tick,
+/// the player takes over once the replay is over
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplaySettings>()
+            .add_startup_system(load_replay)
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(start_recording)
+                    .with_system(rewind_replay),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(save_recording))
+            .stage(FixedUpdateStage, |schedule: &mut Schedule| {
+                schedule.add_system_to_stage(FixedStage::PreTick, feed_replay)
+            })
+            .add_fixed_system_set(SystemSet::new().with_system(record_actions));
+    }
+}
+
+fn load_replay(mut commands: Commands, settings: Res<ReplaySettings>) {
+    let path = match &settings.replay {
+        Some(path) => path,
+        None => return,
+    };
+    let replay = match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(error) => {
+            error!("Failed to load replay {}: {}", path.display(), error);
+            return;
+        }
+    };
+    if replay.version != env!("CARGO_PKG_VERSION") {
+        warn!(
+            "Replay recorded with version {}, it may not play back the same",
+            replay.version
+        );
+    }
+    info!(
+        "Replaying {} ({} ticks, seed {})",
+        path.display(),
+        replay.ticks(),
+        replay.seed
+    );
+
+    commands.insert_resource(RngSeed(Some(replay.seed)));
+    commands.insert_resource(ReplayPlayer::new(replay));
+}
+
+fn rewind_replay(player: Option<ResMut<ReplayPlayer>>) {
+    if let Some(mut player) = player {
+        player.rewind();
+    }
+}
+
+fn start_recording(mut commands: Commands, settings: Res<ReplaySettings>) {
+    if let Some(path) = &settings.record {
+        commands.insert_resource(ReplayRecorder {
+            path: path.clone(),
+            replay: Replay::new(0),
+        });
+    }
+}
+
+/// Runs before the gameplay systems of every tick
+fn feed_replay(player: Option<ResMut<ReplayPlayer>>, mut actions: ResMut<Actions>) {
+    let mut player = match player {
+        Some(player) if !player.finished() => player,
+        _ => return,
+    };
+    *actions = player.next_actions();
+    if player.finished() {
+        info!("Replay over, the player has control again");
+    }
+}
+
+fn record_actions(recorder: Option<ResMut<ReplayRecorder>>, actions: Res<Actions>) {
+    if let Some(mut recorder) = recorder {
+        recorder.replay.push(&actions);
+    }
+}
+
+fn save_recording(
+    mut commands: Commands,
+    recorder: Option<ResMut<ReplayRecorder>>,
+    rng: Res<GameRng>,
+) {
+    let mut recorder = match recorder {
+        Some(recorder) => recorder,
+        None => return,
+    };
+    // the `RngPlugin` picks the seed when entering the run, after the recording started
+    recorder.replay.seed = rng.seed();
+
+    match recorder.replay.save(&recorder.path) {
+        Ok(()) => info!("Replay saved to {}", recorder.path.display()),
+        Err(error) => error!(
+            "Failed to save replay {}: {}",
+            recorder.path.display(),
+            error
+        ),
+    }
+    commands.remove_resource::<ReplayRecorder>();
+}
@@ -0,0 +1,73 @@
+use crate::GameState;
+use bevy::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+pub struct RngPlugin;
+
+/// Seed requested for the next runs, e.g. with `--seed 1234` on the command line
+/// Without it every run gets a fresh random seed
+#[derive(Default)]
+pub struct RngSeed(pub Option<u64>);
+
+impl RngSeed {
+    /// Reads `--seed <u64>` from the command line arguments
+    pub fn from_args() -> Self {
+        let mut args = std::env::args().skip_while(|arg| arg != "--seed").skip(1);
+        RngSeed(args.next().map(|seed| {
+            seed.parse()
+                .unwrap_or_else(|_| panic!("--seed expects an unsigned integer, got '{}'", seed))
+        }))
+    }
+}
+
+/// All gameplay randomness of a run comes from this resource
+/// Every subsystem draws from its own stream, so adding a draw to one of them
+/// doesn't change what the others roll
+pub struct GameRng {
+    seed: u64,
+    pub spawning: ChaCha8Rng,
+    pub loot: ChaCha8Rng,
+    pub upgrades: ChaCha8Rng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        let stream = |n| {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            rng.set_stream(n);
+            rng
+        };
+
+        GameRng {
+            seed,
+            spawning: stream(1),
+            loot: stream(2),
+            upgrades: stream(3),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl FromWorld for GameRng {
+    fn from_world(world: &mut World) -> Self {
+        let seed = world.get_resource::<RngSeed>().and_then(|seed| seed.0);
+        GameRng::new(seed.unwrap_or_else(random))
+    }
+}
+
+/// This plugin reseeds the `GameRng` at the start of every run
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RngSeed>()
+            .init_resource::<GameRng>()
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(reseed));
+    }
+}
+
+fn reseed(seed: Res<RngSeed>, mut rng: ResMut<GameRng>) {
+    *rng = GameRng::new(seed.0.unwrap_or_else(random));
+}
@@ -0,0 +1,554 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::input_map::{Action, ActiveGamepad, InputMap, Inputs};
+use crate::loading::FontAssets;
+use crate::menu::ButtonColors;
+use crate::player::MainCamera;
+use crate::utils::{config_path, despawn_with};
+use crate::{GameConfiguration, GameState};
+use bevy::prelude::*;
+use bevy::window::WindowMode;
+use serde::{Deserialize, Serialize};
+
+const SETTINGS_FILE: &str = "settings.ron";
+
+/// Window sizes offered by the settings menu
+const WINDOW_SIZES: [(f32, f32); 5] = [
+    (800., 600.),
+    (1024., 768.),
+    (1280., 720.),
+    (1600., 900.),
+    (1920., 1080.),
+];
+
+pub struct SettingsPlugin;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowModeSetting {
+    Windowed,
+    BorderlessFullscreen,
+    Fullscreen,
+}
+
+impl From<WindowModeSetting> for WindowMode {
+    fn from(mode: WindowModeSetting) -> Self {
+        match mode {
+            WindowModeSetting::Windowed => WindowMode::Windowed,
+            WindowModeSetting::BorderlessFullscreen => WindowMode::BorderlessFullscreen,
+            WindowModeSetting::Fullscreen => WindowMode::Fullscreen,
+        }
+    }
+}
+
+/// Player preferences, saved in the config directory
+/// Fields missing from the file keep their default value
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub window_mode: WindowModeSetting,
+    pub window_width: f32,
+    pub window_height: f32,
+    pub vsync: bool,
+    /// Volumes are in `0..=1`, music and effects are multiplied by the master volume
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    /// Screen pixels per texture pixel, the camera zooms so that gameplay is unchanged
+    pub pixel_scale: f32,
+    pub screen_shake: bool,
+    pub damage_numbers: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            window_mode: WindowModeSetting::Windowed,
+            window_width: 800.,
+            window_height: 600.,
+            vsync: true,
+            master_volume: 1.,
+            music_volume: 0.3,
+            sfx_volume: 0.5,
+            pixel_scale: 3.,
+            screen_shake: true,
+            damage_numbers: true,
+        }
+    }
+}
+
+impl Settings {
+    pub fn music_volume(&self) -> f32 {
+        self.master_volume * self.music_volume
+    }
+
+    pub fn sfx_volume(&self) -> f32 {
+        self.master_volume * self.sfx_volume
+    }
+
+    fn path() -> Option<PathBuf> {
+        config_path(SETTINGS_FILE)
+    }
+
+    /// The saved settings, or the default ones if there are none
+    pub fn load() -> Self {
+        Settings::path().map_or_else(Settings::default, |path| Settings::load_from(&path))
+    }
+
+    /// The settings saved in `path`, or the default ones if the file is missing or invalid
+    pub fn load_from(path: &Path) -> Self {
+        if !path.exists() {
+            return Settings::default();
+        }
+        match fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| ron::de::from_str(&file).map_err(anyhow::Error::from))
+        {
+            Ok(settings) => settings,
+            Err(error) => {
+                error!("Failed to load {}: {}", path.display(), error);
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        if let Some(path) = Settings::path() {
+            self.save_to(&path);
+        }
+    }
+
+    pub fn save_to(&self, path: &Path) {
+        let pretty = ron::ser::PrettyConfig::default();
+        if let Err(error) = ron::ser::to_string_pretty(self, pretty)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| fs::write(path, file).map_err(anyhow::Error::from))
+        {
+            error!("Failed to save {}: {}", path.display(), error);
+        }
+    }
+
+    pub fn window_descriptor(&self, title: &str) -> WindowDescriptor {
+        WindowDescriptor {
+            width: self.window_width,
+            height: self.window_height,
+            vsync: self.vsync,
+            mode: self.window_mode.into(),
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A line of the settings menu
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingOption {
+    WindowMode,
+    WindowSize,
+    VSync,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    PixelScale,
+    ScreenShake,
+    DamageNumbers,
+}
+
+impl SettingOption {
+    pub const ALL: [SettingOption; 9] = [
+        SettingOption::WindowMode,
+        SettingOption::WindowSize,
+        SettingOption::VSync,
+        SettingOption::MasterVolume,
+        SettingOption::MusicVolume,
+        SettingOption::SfxVolume,
+        SettingOption::PixelScale,
+        SettingOption::ScreenShake,
+        SettingOption::DamageNumbers,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SettingOption::WindowMode => "Window",
+            SettingOption::WindowSize => "Resolution",
+            SettingOption::VSync => "VSync",
+            SettingOption::MasterVolume => "Master volume",
+            SettingOption::MusicVolume => "Music volume",
+            SettingOption::SfxVolume => "Effects volume",
+            SettingOption::PixelScale => "Pixel scale",
+            SettingOption::ScreenShake => "Screen shake",
+            SettingOption::DamageNumbers => "Damage numbers",
+        }
+    }
+
+    pub fn value(&self, settings: &Settings) -> String {
+        let on_off = |on| if on { "On" } else { "Off" }.to_string();
+        let percent = |volume: f32| format!("{}%", (volume * 100.).round());
+        match self {
+            SettingOption::WindowMode => match settings.window_mode {
+                WindowModeSetting::Windowed => "Windowed",
+                WindowModeSetting::BorderlessFullscreen => "Borderless",
+                WindowModeSetting::Fullscreen => "Fullscreen",
+            }
+            .to_string(),
+            SettingOption::WindowSize => {
+                format!("{}x{}", settings.window_width, settings.window_height)
+            }
+            SettingOption::VSync => on_off(settings.vsync),
+            SettingOption::MasterVolume => percent(settings.master_volume),
+            SettingOption::MusicVolume => percent(settings.music_volume),
+            SettingOption::SfxVolume => percent(settings.sfx_volume),
+            SettingOption::PixelScale => format!("x{}", settings.pixel_scale),
+            SettingOption::ScreenShake => on_off(settings.screen_shake),
+            SettingOption::DamageNumbers => on_off(settings.damage_numbers),
+        }
+    }
+
+    /// Moves the option to its next (`step > 0`) or previous (`step < 0`) value
+    /// Lists wrap around, volumes and the pixel scale are clamped
+    pub fn adjust(&self, settings: &mut Settings, step: i32) {
+        let cycle =
+            |index: usize, len: usize| (index as i32 + step).rem_euclid(len as i32) as usize;
+        let volume = |volume: f32| (volume + 0.1 * step as f32).clamp(0., 1.);
+        match self {
+            SettingOption::WindowMode => {
+                let modes = [
+                    WindowModeSetting::Windowed,
+                    WindowModeSetting::BorderlessFullscreen,
+                    WindowModeSetting::Fullscreen,
+                ];
+                let index = modes.iter().position(|m| *m == settings.window_mode);
+                settings.window_mode = modes[cycle(index.unwrap_or(0), modes.len())];
+            }
+            SettingOption::WindowSize => {
+                let size = (settings.window_width, settings.window_height);
+                let index = WINDOW_SIZES.iter().position(|s| *s == size);
+                let (width, height) = WINDOW_SIZES[cycle(index.unwrap_or(0), WINDOW_SIZES.len())];
+                settings.window_width = width;
+                settings.window_height = height;
+            }
+            SettingOption::VSync => settings.vsync = !settings.vsync,
+            SettingOption::MasterVolume => settings.master_volume = volume(settings.master_volume),
+            SettingOption::MusicVolume => settings.music_volume = volume(settings.music_volume),
+            SettingOption::SfxVolume => settings.sfx_volume = volume(settings.sfx_volume),
+            SettingOption::PixelScale => {
+                settings.pixel_scale = (settings.pixel_scale + step as f32).clamp(1., 6.)
+            }
+            SettingOption::ScreenShake => settings.screen_shake = !settings.screen_shake,
+            SettingOption::DamageNumbers => settings.damage_numbers = !settings.damage_numbers,
+        }
+    }
+}
+
+#[derive(Component)]
+struct SettingsUi;
+
+#[derive(Component, Clone, Copy)]
+enum SettingsButton {
+    Adjust(SettingOption, i32),
+    Controls,
+    Back,
+}
+
+/// Rows of the menu: one per `SettingOption`, then Controls and Back
+const SETTINGS_ROWS: usize = SettingOption::ALL.len() + 2;
+
+impl SettingsButton {
+    fn row(&self) -> usize {
+        match self {
+            SettingsButton::Adjust(option, _) => SettingOption::ALL
+                .iter()
+                .position(|o| o == option)
+                .unwrap_or(0),
+            SettingsButton::Controls => SettingOption::ALL.len(),
+            SettingsButton::Back => SettingOption::ALL.len() + 1,
+        }
+    }
+
+    /// Button pressed by `Action::Confirm` on the row
+    fn for_row(row: usize) -> Self {
+        match SettingOption::ALL.get(row) {
+            Some(option) => SettingsButton::Adjust(*option, 1),
+            None if row == SettingOption::ALL.len() => SettingsButton::Controls,
+            None => SettingsButton::Back,
+        }
+    }
+}
+
+/// Row highlighted by keyboard, gamepad or mouse
+#[derive(Default)]
+struct SelectedSetting {
+    row: usize,
+    /// False on the frame the menu is opened or returned to, the key that led here is still pressed
+    ready: bool,
+}
+
+/// This plugin applies the `Settings` and draws the settings menu during `GameState::Settings`,
+/// opened from the main menu or pushed over the pause menu
+/// Every change is saved right away
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedSetting>()
+            .add_system(apply_window_settings)
+            .add_system(apply_pixel_scale)
+            .add_system_set(SystemSet::on_enter(GameState::Settings).with_system(open_settings))
+            .add_system_set(SystemSet::on_resume(GameState::Settings).with_system(resume_settings))
+            .add_system_set(
+                SystemSet::on_update(GameState::Settings)
+                    .with_system(rebuild_settings_ui)
+                    .with_system(navigate_settings_menu),
+            )
+            .add_system_set(
+                SystemSet::on_pause(GameState::Settings).with_system(despawn_with::<SettingsUi>),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Settings).with_system(despawn_with::<SettingsUi>),
+            );
+    }
+}
+
+fn apply_window_settings(settings: Res<Settings>, windows: Option<ResMut<Windows>>) {
+    let mut windows = match windows {
+        Some(windows) if settings.is_changed() => windows,
+        _ => return,
+    };
+    if let Some(window) = windows.get_primary_mut() {
+        window.set_mode(settings.window_mode.into());
+        window.set_resolution(settings.window_width, settings.window_height);
+        window.set_vsync(settings.vsync);
+    }
+}
+
+/// Sprites are scaled by `GameConfiguration::scale`, the camera zooms to show them at `pixel_scale`
+fn apply_pixel_scale(
+    settings: Res<Settings>,
+    config: Res<GameConfiguration>,
+    mut cameras: Query<&mut OrthographicProjection, With<MainCamera>>,
+) {
+    let scale = config.scale / settings.pixel_scale.max(1.);
+    for mut projection in cameras.iter_mut() {
+        if projection.scale != scale {
+            projection.scale = scale;
+        }
+    }
+}
+
+fn rebuild_settings_ui(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    font_assets: Res<FontAssets>,
+    button_colors: Res<ButtonColors>,
+    ui: Query<Entity, With<SettingsUi>>,
+) {
+    if !settings.is_changed() && ui.iter().next().is_some() {
+        return;
+    }
+    for e in ui.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+
+    let text_style = |font_size| TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: Color::rgb(0.4, 0.4, 0.4).into(),
+            ..Default::default()
+        })
+        .insert(SettingsUi)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(10.)),
+                    ..Default::default()
+                },
+                text: Text::with_section("Settings", text_style(40.), Default::default()),
+                ..Default::default()
+            });
+
+            for option in SettingOption::ALL {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        color: Color::NONE.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        let value = option.value(&settings);
+                        for (text, width) in [(option.name(), 180.), (value.as_str(), 140.)] {
+                            parent.spawn_bundle(TextBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(width), Val::Auto),
+                                    ..Default::default()
+                                },
+                                text: Text::with_section(text, text_style(22.), Default::default()),
+                                ..Default::default()
+                            });
+                        }
+                        for (step, label) in [(-1, "<"), (1, ">")] {
+                            spawn_button(
+                                parent,
+                                SettingsButton::Adjust(option, step),
+                                label,
+                                40.,
+                                text_style(22.),
+                                &button_colors,
+                            );
+                        }
+                    });
+            }
+
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(10.)),
+                        ..Default::default()
+                    },
+                    color: Color::NONE.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for (button, label) in [
+                        (SettingsButton::Controls, "Controls"),
+                        (SettingsButton::Back, "Back"),
+                    ] {
+                        spawn_button(parent, button, label, 140., text_style(28.), &button_colors);
+                    }
+                });
+        });
+}
+
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    button: SettingsButton,
+    label: &str,
+    width: f32,
+    text_style: TextStyle,
+    button_colors: &ButtonColors,
+) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(width), Val::Px(text_style.font_size + 8.)),
+                margin: Rect::all(Val::Px(2.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: button_colors.normal,
+            ..Default::default()
+        })
+        .insert(button)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(label, text_style, Default::default()),
+                ..Default::default()
+            });
+        });
+}
+
+fn open_settings(mut selected: ResMut<SelectedSetting>) {
+    *selected = SelectedSetting::default();
+}
+
+fn resume_settings(mut selected: ResMut<SelectedSetting>) {
+    selected.ready = false;
+}
+
+type SettingsButtonInteraction<'a> = (&'a SettingsButton, &'a Interaction, &'a mut UiColor);
+
+/// Up and down select a row, left and right adjust its setting, confirm presses its button
+/// and cancel goes back like the Back button
+#[allow(clippy::too_many_arguments)]
+fn navigate_settings_menu(
+    input_map: Res<InputMap>,
+    active: Res<ActiveGamepad>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    button_colors: Res<ButtonColors>,
+    mut selected: ResMut<SelectedSetting>,
+    mut settings: ResMut<Settings>,
+    mut state: ResMut<State<GameState>>,
+    clicked: Query<(&SettingsButton, &Interaction), Changed<Interaction>>,
+    mut buttons: Query<SettingsButtonInteraction>,
+) {
+    if !selected.ready {
+        selected.ready = true;
+        return;
+    }
+    let inputs = Inputs {
+        keys: &keyboard_input,
+        mouse: &mouse_input,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad: active.gamepad,
+    };
+    let mut chosen = None;
+
+    if input_map.just_pressed(Action::MoveUp, inputs) {
+        selected.row = (selected.row + SETTINGS_ROWS - 1) % SETTINGS_ROWS;
+    }
+    if input_map.just_pressed(Action::MoveDown, inputs) {
+        selected.row = (selected.row + 1) % SETTINGS_ROWS;
+    }
+    if let Some(option) = SettingOption::ALL.get(selected.row) {
+        if input_map.just_pressed(Action::MoveLeft, inputs) {
+            chosen = Some(SettingsButton::Adjust(*option, -1));
+        }
+        if input_map.just_pressed(Action::MoveRight, inputs) {
+            chosen = Some(SettingsButton::Adjust(*option, 1));
+        }
+    }
+    if input_map.just_pressed(Action::Confirm, inputs) {
+        chosen = Some(SettingsButton::for_row(selected.row));
+    }
+    if input_map.just_pressed(Action::Cancel, inputs) {
+        chosen = Some(SettingsButton::Back);
+    }
+
+    for (button, interaction) in clicked.iter() {
+        match *interaction {
+            Interaction::Clicked => chosen = Some(*button),
+            Interaction::Hovered => selected.row = button.row(),
+            Interaction::None => {}
+        }
+    }
+
+    for (button, _interaction, mut color) in buttons.iter_mut() {
+        *color = if button.row() == selected.row {
+            button_colors.hovered
+        } else {
+            button_colors.normal
+        };
+    }
+
+    match chosen {
+        Some(SettingsButton::Adjust(option, step)) => {
+            option.adjust(&mut settings, step);
+            settings.save();
+        }
+        Some(SettingsButton::Controls) => {
+            let _ = state.push(GameState::Controls);
+        }
+        Some(SettingsButton::Back) => {
+            // back to the pause menu when pushed over it, or else to the main menu
+            if state.pop().is_err() {
+                let _ = state.set(GameState::Menu);
+            }
+        }
+        None => {}
+    }
+}
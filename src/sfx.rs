@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use crate::timestep::{FixedTimestepAppExt, SimTime};
+use crate::GameState;
+use bevy::prelude::*;
+
+pub struct SfxPlugin;
+
+/// Something that happened in the game and makes a sound
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sfx {
+    Hit,
+    EnemyDeath,
+    XpPickup,
+    LevelUp,
+    PlayerHurt,
+}
+
+/// Sent by the gameplay systems, the `SfxMap` decides which sound is played, if any
+pub struct PlaySfx(pub Sfx);
+
+/// A sound picked for a `PlaySfx` event, played by the audio plugin
+pub struct PlaySound {
+    pub path: String,
+}
+
+/// Sounds of an effect and how often it can play
+pub struct SfxSound {
+    /// Variations of the sound, played in turn
+    pub paths: Vec<String>,
+    /// Instances of the sound playing at the same time
+    pub max_instances: usize,
+    /// Seconds between two instances of the sound
+    pub min_interval: f64,
+    /// Seconds an instance counts as playing
+    pub duration: f64,
+}
+
+impl SfxSound {
+    fn new(paths: &[&str], max_instances: usize, min_interval: f64, duration: f64) -> Self {
+        SfxSound {
+            paths: paths.iter().map(|path| path.to_string()).collect(),
+            max_instances,
+            min_interval,
+            duration,
+        }
+    }
+}
+
+/// Sounds of each effect, effects without any are silent
+pub struct SfxMap(pub HashMap<Sfx, SfxSound>);
+
+/// No sound effect is shipped in `assets/audio` yet, the effects are silent until their
+/// files are listed here
+impl Default for SfxMap {
+    fn default() -> Self {
+        SfxMap(HashMap::from([
+            (Sfx::Hit, SfxSound::new(&[], 4, 0.05, 0.2)),
+            (Sfx::EnemyDeath, SfxSound::new(&[], 4, 0.05, 0.4)),
+            (Sfx::XpPickup, SfxSound::new(&[], 3, 0.04, 0.2)),
+            (Sfx::LevelUp, SfxSound::new(&[], 1, 0., 1.)),
+            (Sfx::PlayerHurt, SfxSound::new(&[], 1, 0.2, 0.3)),
+        ]))
+    }
+}
+
+impl SfxMap {
+    /// All the sound files, so that they can be loaded before they are played
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.0
+            .values()
+            .flat_map(|sound| sound.paths.iter().map(String::as_str))
+    }
+}
+
+/// Limits how often and how many instances of each sound play,
+/// so that a hundred hits in the same tick don't play a hundred sounds
+#[derive(Default)]
+pub struct SfxThrottle {
+    /// Start time of the instances that may still be playing
+    playing: HashMap<Sfx, Vec<f64>>,
+    /// Next variation of each sound
+    next: HashMap<Sfx, usize>,
+}
+
+impl SfxThrottle {
+    /// The sound to play for the effect at time `now`, in seconds,
+    /// or `None` if it played too recently or too many instances are playing
+    pub fn select<'a>(&mut self, map: &'a SfxMap, sfx: Sfx, now: f64) -> Option<&'a str> {
+        let sound = map.0.get(&sfx).filter(|sound| !sound.paths.is_empty())?;
+        let playing = self.playing.entry(sfx).or_default();
+        playing.retain(|start| start + sound.duration > now);
+
+        let too_soon = playing
+            .last()
+            .map_or(false, |last| now - last < sound.min_interval);
+        if too_soon || playing.len() >= sound.max_instances {
+            return None;
+        }
+        playing.push(now);
+
+        let next = self.next.entry(sfx).or_default();
+        let path = &sound.paths[*next % sound.paths.len()];
+        *next += 1;
+        Some(path)
+    }
+}
+
+/// This plugin turns the `PlaySfx` events sent by the gameplay into `PlaySound` events
+/// It doesn't play anything, so that headless simulations can check the sounds too
+impl Plugin for SfxPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SfxMap>()
+            .init_resource::<SfxThrottle>()
+            .add_fixed_event::<PlaySfx>()
+            // read by the audio plugin once per frame
+            .add_event::<PlaySound>()
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(reset_sfx_throttle))
+            .add_fixed_system_set(SystemSet::new().with_system(select_sfx));
+    }
+}
+
+/// The simulation clock starts over with every run
+fn reset_sfx_throttle(mut throttle: ResMut<SfxThrottle>) {
+    *throttle = SfxThrottle::default();
+}
+
+fn select_sfx(
+    time: Res<SimTime>,
+    map: Res<SfxMap>,
+    mut throttle: ResMut<SfxThrottle>,
+    mut event_sfx: EventReader<PlaySfx>,
+    mut event_sound: EventWriter<PlaySound>,
+) {
+    let now = time.ticks() as f64 * time.step().as_secs_f64();
+    for PlaySfx(sfx) in event_sfx.iter() {
+        if let Some(path) = throttle.select(&map, *sfx, now) {
+            event_sound.send(PlaySound {
+                path: path.to_string(),
+            });
+        }
+    }
+}
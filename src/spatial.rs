@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::collide_aabb::collide;
+use crate::enemy::{Alive, Enemy};
+use crate::player::{HurtBox, XpGem};
+use crate::timestep::FixedTimestepAppExt;
+use crate::GameState;
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+
+pub struct SpatialPlugin;
+
+/// Enemies and gems are indexed in `SpatialHash<Enemy>` and `SpatialHash<XpGem>` by this label
+/// Systems querying the indexes should run after it
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub struct UpdateSpatialHash;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SpatialEntry {
+    pub entity: Entity,
+    /// Center of the indexed box
+    pub pos: Vec2,
+    pub size: Vec2,
+}
+
+/// Uniform grid of boxes, bucketed by the cell of their center
+/// `T` is the marker component of the indexed entities
+pub struct SpatialHash<T> {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<SpatialEntry>>,
+    len: usize,
+    /// Largest half size of the indexed boxes, a box can reach that far out of its cell
+    max_half_size: Vec2,
+    marker: PhantomData<T>,
+}
+
+impl<T> SpatialHash<T> {
+    pub fn new(cell_size: f32) -> Self {
+        SpatialHash {
+            cell_size,
+            cells: HashMap::new(),
+            len: 0,
+            max_half_size: Vec2::ZERO,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes every entry, but keeps the allocations of the cells that were used since the last clear
+    /// Cells left empty are dropped, so that the map follows the entities instead of
+    /// growing with all the ground they ever covered
+    pub fn clear(&mut self) {
+        self.cells.retain(|_, entries| {
+            let used = !entries.is_empty();
+            entries.clear();
+            used
+        });
+        self.len = 0;
+        self.max_half_size = Vec2::ZERO;
+    }
+
+    pub fn insert(&mut self, entity: Entity, pos: Vec2, size: Vec2) {
+        self.cells
+            .entry(self.cell(pos))
+            .or_default()
+            .push(SpatialEntry { entity, pos, size });
+        self.len += 1;
+        self.max_half_size = self.max_half_size.max(size / 2.);
+    }
+
+    fn cell(&self, pos: Vec2) -> (i32, i32) {
+        (
+            (pos.x / self.cell_size).floor() as i32,
+            (pos.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    /// Entries of all the cells covering the rectangle from `min` to `max`
+    fn entries_in(&self, min: Vec2, max: Vec2) -> impl Iterator<Item = &SpatialEntry> {
+        let (min_x, min_y) = self.cell(min);
+        let (max_x, max_y) = self.cell(max);
+
+        (min_x..=max_x)
+            .flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+            .filter_map(move |cell| self.cells.get(&cell))
+            .flatten()
+    }
+
+    /// All the boxes overlapping the box of `size` centered on `pos`
+    pub fn overlapping(&self, pos: Vec2, size: Vec2) -> impl Iterator<Item = &SpatialEntry> {
+        let reach = size / 2. + self.max_half_size;
+
+        self.entries_in(pos - reach, pos + reach)
+            .filter(move |entry| {
+                collide(pos.extend(0.), size, entry.pos.extend(0.), entry.size).is_some()
+            })
+    }
+
+    /// All the boxes whose center is at most `radius` away from `pos`
+    pub fn within_radius(&self, pos: Vec2, radius: f32) -> impl Iterator<Item = &SpatialEntry> {
+        let reach = Vec2::splat(radius);
+
+        self.entries_in(pos - reach, pos + reach)
+            .filter(move |entry| (entry.pos - pos).length() <= radius)
+    }
+
+    /// The `k` boxes whose center is the closest to `pos`, with their distance, closest first
+    pub fn nearest(&self, pos: Vec2, k: usize) -> Vec<(SpatialEntry, f32)> {
+        let mut found = Vec::new();
+        if k == 0 {
+            return found;
+        }
+
+        let (cx, cy) = self.cell(pos);
+        let mut ring = 0;
+        loop {
+            // cells at a Chebyshev distance of `ring` from the cell of `pos`
+            for x in (cx - ring)..=(cx + ring) {
+                for y in (cy - ring)..=(cy + ring) {
+                    if (x - cx).abs() != ring && (y - cy).abs() != ring {
+                        continue;
+                    }
+                    if let Some(entries) = self.cells.get(&(x, y)) {
+                        found.extend(entries.iter().map(|e| (*e, (e.pos - pos).length())));
+                    }
+                }
+            }
+
+            if found.len() == self.len {
+                break;
+            }
+            // entries of the next rings are at least `ring` cells away
+            if found.len() >= k {
+                found.sort_by(|a, b| a.1.total_cmp(&b.1));
+                if found[k - 1].1 <= ring as f32 * self.cell_size {
+                    break;
+                }
+            }
+            ring += 1;
+        }
+
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found.truncate(k);
+        found
+    }
+}
+
+/// This plugin indexes the hurt boxes of living enemies and the positions of the gems every tick
+/// The indexes are only updated during the State `GameState::Playing`
+impl Plugin for SpatialPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SpatialHash::<Enemy>::new(64.))
+            .insert_resource(SpatialHash::<XpGem>::new(64.))
+            .add_fixed_system_set(
+                SystemSet::new()
+                    .label(UpdateSpatialHash)
+                    .with_system(index_enemies)
+                    .with_system(index_gems),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing)
+                    .with_system(clear::<Enemy>)
+                    .with_system(clear::<XpGem>),
+            );
+    }
+}
+
+fn index_enemies(
+    mut hash: ResMut<SpatialHash<Enemy>>,
+    enemies: Query<(Entity, &Transform, &HurtBox), (With<Enemy>, With<Alive>)>,
+) {
+    hash.clear();
+    for (entity, transform, hurt_box) in enemies.iter() {
+        hash.insert(
+            entity,
+            transform.translation.xy() + hurt_box.pos,
+            hurt_box.size,
+        );
+    }
+}
+
+fn index_gems(
+    mut hash: ResMut<SpatialHash<XpGem>>,
+    gems: Query<(Entity, &Transform), With<XpGem>>,
+) {
+    hash.clear();
+    for (entity, transform) in gems.iter() {
+        hash.insert(entity, transform.translation.xy(), Vec2::ZERO);
+    }
+}
+
+fn clear<T: Component>(mut hash: ResMut<SpatialHash<T>>) {
+    hash.clear();
+}
@@ -0,0 +1,235 @@
+use std::time::Duration;
+
+use crate::GameState;
+use bevy::ecs::schedule::ShouldRun;
+use bevy::ecs::system::Resource;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+pub struct TimestepPlugin;
+
+/// Gameplay runs at this rate, whatever the frame rate
+pub const TICKS_PER_SECOND: u32 = 60;
+
+/// At most this many ticks are simulated in a single frame, a slower frame drops the extra time
+const MAX_TICKS_PER_FRAME: u32 = 8;
+
+/// Runs after `CoreStage::Update`, as many times per frame as there are ticks to simulate
+/// Ticks only accumulate while the State is `GameState::Playing`, so its systems don't need
+/// `SystemSet::on_update` (Bevy's state run criteria only work in the stage of the state driver)
+#[derive(StageLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub struct FixedUpdateStage;
+
+/// Stages of a single tick, inside the `FixedUpdateStage`
+#[derive(StageLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub enum FixedStage {
+    /// Fixed events are updated and interpolated transforms are snapshotted
+    PreTick,
+    /// Gameplay systems
+    Tick,
+}
+
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub struct Interpolate;
+
+/// How the simulation clock advances
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SimMode {
+    /// Follows the frame time
+    RealTime,
+    /// Runs exactly this many ticks per frame, for headless simulation and tests
+    Manual(u32),
+}
+
+/// Clock of the fixed timestep simulation
+/// Gameplay systems read it instead of `Time`, every tick lasts exactly `step()`
+pub struct SimTime {
+    pub mode: SimMode,
+    step: Duration,
+    accumulator: Duration,
+    /// Ticks left to run in the current frame
+    pending: Option<u32>,
+    ticks: u64,
+}
+
+impl Default for SimTime {
+    fn default() -> Self {
+        SimTime {
+            mode: SimMode::RealTime,
+            step: Duration::from_secs(1) / TICKS_PER_SECOND,
+            accumulator: Duration::ZERO,
+            pending: None,
+            ticks: 0,
+        }
+    }
+}
+
+impl SimTime {
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Duration of a tick, same interface as `Time` for the gameplay systems
+    pub fn delta(&self) -> Duration {
+        self.step
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    /// Number of ticks simulated since the start of the run
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// How far the frame is between the last tick and the next one, in `0..1`
+    pub fn alpha(&self) -> f32 {
+        match self.mode {
+            SimMode::RealTime => self.accumulator.as_secs_f32() / self.step.as_secs_f32(),
+            SimMode::Manual(_) => 1.,
+        }
+    }
+}
+
+/// Transforms of the entity are interpolated between the last two ticks when rendering
+/// Only the translation is interpolated
+#[derive(Component, Default)]
+pub struct Interpolated {
+    previous: Option<Vec3>,
+    current: Option<Vec3>,
+}
+
+pub trait FixedTimestepAppExt {
+    /// Adds a system set to the `FixedStage::Tick` stage, it runs every tick of `GameState::Playing`
+    fn add_fixed_system_set(&mut self, system_set: SystemSet) -> &mut Self;
+
+    /// Like `add_event`, but the event buffers are swapped every tick instead of every frame,
+    /// so that fixed systems can't miss events when several frames go by without a tick
+    fn add_fixed_event<T: Resource>(&mut self) -> &mut Self;
+}
+
+impl FixedTimestepAppExt for App {
+    fn add_fixed_system_set(&mut self, system_set: SystemSet) -> &mut Self {
+        self.stage(FixedUpdateStage, |schedule: &mut Schedule| {
+            schedule.add_system_set_to_stage(FixedStage::Tick, system_set)
+        })
+    }
+
+    fn add_fixed_event<T: Resource>(&mut self) -> &mut Self {
+        if !self.world.contains_resource::<Events<T>>() {
+            self.init_resource::<Events<T>>()
+                .stage(FixedUpdateStage, |schedule: &mut Schedule| {
+                    schedule.add_system_to_stage(FixedStage::PreTick, Events::<T>::update_system)
+                });
+        }
+        self
+    }
+}
+
+/// This plugin runs the gameplay systems on a fixed timestep and interpolates their movements
+/// Gameplay systems are added with `add_fixed_system_set`, and keep their `GameState` run criteria
+impl Plugin for TimestepPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimTime>()
+            .add_stage_after(
+                CoreStage::Update,
+                FixedUpdateStage,
+                Schedule::default()
+                    .with_run_criteria(run_ticks.system())
+                    .with_stage(FixedStage::PreTick, SystemStage::parallel())
+                    .with_stage(FixedStage::Tick, SystemStage::parallel()),
+            )
+            .stage(FixedUpdateStage, |schedule: &mut Schedule| {
+                schedule.add_system_to_stage(FixedStage::PreTick, snapshot_transforms)
+            })
+            .add_system_to_stage(CoreStage::PreUpdate, restore_transforms)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                interpolate_transforms
+                    .label(Interpolate)
+                    .before(TransformSystem::TransformPropagate),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(reset_sim_time));
+    }
+}
+
+/// Run criteria for systems outside of the `CoreStage::Update` stage
+pub(crate) fn playing(state: Res<State<GameState>>) -> ShouldRun {
+    if state.current() == &GameState::Playing {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+fn reset_sim_time(mut sim: ResMut<SimTime>) {
+    sim.accumulator = Duration::ZERO;
+    sim.ticks = 0;
+}
+
+/// Run criteria of the `FixedUpdateStage`
+/// Called again after every tick, the number of ticks is decided on the first call of the frame
+fn run_ticks(time: Res<Time>, state: Res<State<GameState>>, mut sim: ResMut<SimTime>) -> ShouldRun {
+    let pending = match sim.pending {
+        // a tick queued a transition, which is only applied in `CoreStage::Update`,
+        // the remaining ticks of the frame would keep playing a run that is paused or over
+        Some(_) if state.is_changed() => 0,
+        Some(pending) => pending,
+        None => {
+            // the accumulator is kept while paused, so that the run resumes exactly where it was
+            if state.current() != &GameState::Playing {
+                return ShouldRun::No;
+            }
+            match sim.mode {
+                SimMode::RealTime => {
+                    sim.accumulator += time.delta();
+                    let ticks = (sim.accumulator.as_secs_f64() / sim.step.as_secs_f64()) as u32;
+                    let step = sim.step;
+                    sim.accumulator -= step * ticks;
+                    if ticks > MAX_TICKS_PER_FRAME {
+                        sim.accumulator = Duration::ZERO;
+                    }
+                    ticks.min(MAX_TICKS_PER_FRAME)
+                }
+                SimMode::Manual(ticks) => ticks,
+            }
+        }
+    };
+
+    if pending == 0 {
+        sim.pending = None;
+        ShouldRun::No
+    } else {
+        sim.pending = Some(pending - 1);
+        sim.ticks += 1;
+        ShouldRun::YesAndCheckAgain
+    }
+}
+
+fn snapshot_transforms(mut q: Query<(&Transform, &mut Interpolated)>) {
+    for (transform, mut interpolated) in q.iter_mut() {
+        interpolated.previous = Some(transform.translation);
+    }
+}
+
+/// Puts back the simulated translations, overwritten by `interpolate_transforms` for rendering
+fn restore_transforms(mut q: Query<(&mut Transform, &Interpolated)>) {
+    for (mut transform, interpolated) in q.iter_mut() {
+        if let Some(current) = interpolated.current {
+            transform.translation = current;
+        }
+    }
+}
+
+fn interpolate_transforms(sim: Res<SimTime>, mut q: Query<(&mut Transform, &mut Interpolated)>) {
+    let alpha = sim.alpha();
+
+    for (mut transform, mut interpolated) in q.iter_mut() {
+        let current = transform.translation;
+        interpolated.current = Some(current);
+        if let Some(previous) = interpolated.previous {
+            transform.translation = previous.lerp(current, alpha);
+        }
+    }
+}
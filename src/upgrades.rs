@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+
+use crate::input_map::{Action, ActiveGamepad, InputMap, Inputs};
+use crate::loading::FontAssets;
+use crate::menu::ButtonColors;
+use crate::player::{BaseMoveSpeed, Health, MaxHealth, PickupRadius, Player};
+use crate::progression::PendingLevelUps;
+use crate::replay::{ReplayPlayer, ReplayRecorder};
+use crate::rng::GameRng;
+use crate::weapon::{Weapon, WeaponKind, WeaponModifiers, MAX_WEAPON_LEVEL};
+use crate::GameState;
+use bevy::prelude::*;
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+
+pub struct UpgradePlugin;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Upgrade {
+    MoveSpeed,
+    Damage,
+    AttackSpeed,
+    Area,
+    Heal,
+    MaxHealth,
+    Magnet,
+    /// Equips the weapon, or levels it up if it is already equipped
+    Weapon(WeaponKind),
+}
+
+impl Upgrade {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Upgrade::MoveSpeed => "Swiftness",
+            Upgrade::Damage => "Power",
+            Upgrade::AttackSpeed => "Haste",
+            Upgrade::Area => "Reach",
+            Upgrade::Heal => "Vitality",
+            Upgrade::MaxHealth => "Fortitude",
+            Upgrade::Magnet => "Magnet",
+            Upgrade::Weapon(kind) => kind.name(),
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Upgrade::MoveSpeed => "+10% move speed",
+            Upgrade::Damage => "+10% weapon damage",
+            Upgrade::AttackSpeed => "-10% weapon cooldown",
+            Upgrade::Area => "+10% weapon area",
+            Upgrade::Heal => "Restore 25 health",
+            Upgrade::MaxHealth => "+20 max health",
+            Upgrade::Magnet => "+25% pickup radius",
+            Upgrade::Weapon(kind) => kind.description(),
+        }
+    }
+}
+
+/// Upgrades that can be offered on level-up, with their relative weights
+pub struct UpgradePool {
+    pub entries: Vec<(Upgrade, f32)>,
+    pub min_offers: usize,
+    pub max_offers: usize,
+}
+
+impl Default for UpgradePool {
+    fn default() -> Self {
+        let mut entries = vec![
+            (Upgrade::MoveSpeed, 1.),
+            (Upgrade::Damage, 1.),
+            (Upgrade::AttackSpeed, 1.),
+            (Upgrade::Area, 0.75),
+            (Upgrade::Heal, 0.5),
+            (Upgrade::MaxHealth, 0.75),
+            (Upgrade::Magnet, 0.75),
+        ];
+        entries.extend(
+            WeaponKind::ALL
+                .iter()
+                .map(|&kind| (Upgrade::Weapon(kind), 1.)),
+        );
+
+        UpgradePool {
+            entries,
+            min_offers: 3,
+            max_offers: 4,
+        }
+    }
+}
+
+impl UpgradePool {
+    /// Draws distinct upgrades among the `available` ones,
+    /// each pick weighted by its entry in the pool
+    pub fn roll(&self, rng: &mut impl Rng, available: impl Fn(&Upgrade) -> bool) -> Vec<Upgrade> {
+        let n = rng.gen_range(self.min_offers..=self.max_offers);
+        let mut candidates: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(upgrade, _)| available(upgrade))
+            .copied()
+            .collect();
+        let mut offers = Vec::with_capacity(n);
+
+        while offers.len() < n {
+            let index = match WeightedIndex::new(candidates.iter().map(|(_, weight)| *weight)) {
+                Ok(dist) => dist.sample(rng),
+                Err(_) => break,
+            };
+            offers.push(candidates.swap_remove(index).0);
+        }
+
+        offers
+    }
+}
+
+/// Upgrades currently shown on the level-up screen
+#[derive(Default)]
+pub struct UpgradeOffer(pub Vec<Upgrade>);
+
+/// Index of the offered upgrade highlighted by keyboard or mouse
+#[derive(Default)]
+struct SelectedUpgrade(usize);
+
+#[derive(Component)]
+struct LevelUpUi;
+
+#[derive(Component)]
+struct UpgradeButton(usize);
+
+/// This plugin draws the level-up screen during `GameState::LevelUp`
+/// and applies the chosen upgrade to the player
+impl Plugin for UpgradePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<UpgradePool>()
+            .init_resource::<UpgradeOffer>()
+            .init_resource::<SelectedUpgrade>()
+            .add_system_set(SystemSet::on_enter(GameState::LevelUp).with_system(roll_offer))
+            .add_system_set(
+                SystemSet::on_update(GameState::LevelUp)
+                    .with_system(rebuild_offer_ui)
+                    .with_system(select_upgrade),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::LevelUp).with_system(cleanup_offer_ui));
+    }
+}
+
+/// Weapons can't be offered past their max level
+fn is_available(upgrade: &Upgrade, weapon_levels: &HashMap<WeaponKind, u32>) -> bool {
+    match upgrade {
+        Upgrade::Weapon(kind) => weapon_levels
+            .get(kind)
+            .map_or(true, |&level| level < MAX_WEAPON_LEVEL),
+        _ => true,
+    }
+}
+
+fn weapon_levels<'a>(
+    player: Entity,
+    weapons: impl Iterator<Item = &'a Weapon>,
+) -> HashMap<WeaponKind, u32> {
+    weapons
+        .filter(|weapon| weapon.owner == player)
+        .map(|weapon| (weapon.kind, weapon.level))
+        .collect()
+}
+
+fn roll_offer(
+    pool: Res<UpgradePool>,
+    mut offer: ResMut<UpgradeOffer>,
+    mut selected: ResMut<SelectedUpgrade>,
+    mut rng: ResMut<GameRng>,
+    player: Query<Entity, With<Player>>,
+    weapons: Query<&Weapon>,
+) {
+    let levels = weapon_levels(player.single(), weapons.iter());
+
+    offer.0 = pool.roll(&mut rng.upgrades, |upgrade| is_available(upgrade, &levels));
+    selected.0 = 0;
+}
+
+fn rebuild_offer_ui(
+    mut commands: Commands,
+    offer: Res<UpgradeOffer>,
+    selected: Res<SelectedUpgrade>,
+    font_assets: Res<FontAssets>,
+    button_colors: Res<ButtonColors>,
+    ui: Query<Entity, With<LevelUpUi>>,
+) {
+    if !offer.is_changed() {
+        return;
+    }
+    for e in ui.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+
+    let text_style = |font_size| TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(LevelUpUi)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(10.)),
+                    ..Default::default()
+                },
+                text: Text::with_section("Level up!", text_style(40.), Default::default()),
+                ..Default::default()
+            });
+
+            for (i, upgrade) in offer.0.iter().enumerate() {
+                let color = if i == selected.0 {
+                    button_colors.hovered
+                } else {
+                    button_colors.normal
+                };
+
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(320.0), Val::Px(60.0)),
+                            margin: Rect::all(Val::Px(5.)),
+                            flex_direction: FlexDirection::ColumnReverse,
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        color,
+                        ..Default::default()
+                    })
+                    .insert(UpgradeButton(i))
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                format!("{}. {}", i + 1, upgrade.name()),
+                                text_style(24.),
+                                Default::default(),
+                            ),
+                            ..Default::default()
+                        });
+                        parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                upgrade.description(),
+                                text_style(16.),
+                                Default::default(),
+                            ),
+                            ..Default::default()
+                        });
+                    });
+            }
+        });
+}
+
+type UpgradeButtonInteraction<'a> = (&'a UpgradeButton, &'a Interaction, &'a mut UiColor);
+
+#[allow(clippy::too_many_arguments)]
+fn select_upgrade(
+    mut commands: Commands,
+    (input_map, active, keyboard_input, mouse_input, gamepad_buttons): (
+        Res<InputMap>,
+        Res<ActiveGamepad>,
+        Res<Input<KeyCode>>,
+        Res<Input<MouseButton>>,
+        Res<Input<GamepadButton>>,
+    ),
+    button_colors: Res<ButtonColors>,
+    pool: Res<UpgradePool>,
+    mut offer: ResMut<UpgradeOffer>,
+    mut selected: ResMut<SelectedUpgrade>,
+    mut pending: ResMut<PendingLevelUps>,
+    mut rng: ResMut<GameRng>,
+    mut state: ResMut<State<GameState>>,
+    clicked: Query<(&UpgradeButton, &Interaction), Changed<Interaction>>,
+    mut buttons: Query<UpgradeButtonInteraction, With<Button>>,
+    mut player: Query<
+        (
+            Entity,
+            &mut BaseMoveSpeed,
+            &mut Health,
+            &mut MaxHealth,
+            &mut WeaponModifiers,
+            &mut PickupRadius,
+        ),
+        With<Player>,
+    >,
+    mut weapons: Query<&mut Weapon>,
+    mut replay: Option<ResMut<ReplayPlayer>>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+) {
+    let n = offer.0.len();
+    if n == 0 {
+        return;
+    }
+
+    let inputs = Inputs {
+        keys: &keyboard_input,
+        mouse: &mouse_input,
+        gamepad_buttons: &gamepad_buttons,
+        gamepad: active.gamepad,
+    };
+    let mut chosen = None;
+
+    // the ability actions pick the offers directly
+    for (i, action) in [
+        Action::Ability1,
+        Action::Ability2,
+        Action::Ability3,
+        Action::Ability4,
+    ]
+    .iter()
+    .enumerate()
+    {
+        if i < n && input_map.just_pressed(*action, inputs) {
+            chosen = Some(i);
+        }
+    }
+    if input_map.just_pressed(Action::MoveUp, inputs) {
+        selected.0 = (selected.0 + n - 1) % n;
+    }
+    if input_map.just_pressed(Action::MoveDown, inputs) {
+        selected.0 = (selected.0 + 1) % n;
+    }
+    if input_map.just_pressed(Action::Confirm, inputs) {
+        chosen = Some(selected.0);
+    }
+
+    for (button, interaction) in clicked.iter() {
+        match *interaction {
+            Interaction::Clicked => chosen = Some(button.0),
+            Interaction::Hovered => selected.0 = button.0,
+            Interaction::None => {}
+        }
+    }
+
+    for (button, _interaction, mut color) in buttons.iter_mut() {
+        *color = if button.0 == selected.0 {
+            button_colors.hovered
+        } else {
+            button_colors.normal
+        };
+    }
+
+    // a replay picks the recorded upgrades, whatever the player input,
+    // the player picks again once they run out
+    if let Some(i) = replay.as_mut().and_then(|replay| replay.next_upgrade()) {
+        chosen = Some(i.min(n - 1));
+    }
+
+    let i = match chosen {
+        Some(i) => i,
+        None => return,
+    };
+    if let Some(mut recorder) = recorder {
+        recorder.replay.upgrades.push(i);
+    }
+    let upgrade = offer.0[i];
+    let (player, mut speed, mut health, mut max_health, mut modifiers, mut pickup_radius) =
+        player.single_mut();
+    match upgrade {
+        Upgrade::MoveSpeed => speed.0 *= 1.1,
+        Upgrade::Damage => modifiers.damage += 0.1,
+        Upgrade::AttackSpeed => modifiers.cooldown *= 0.9,
+        Upgrade::Area => modifiers.area += 0.1,
+        Upgrade::Heal => health.0 = (health.0 + 25.).min(max_health.0),
+        Upgrade::MaxHealth => {
+            max_health.0 += 20.;
+            health.0 += 20.;
+        }
+        Upgrade::Magnet => pickup_radius.0 *= 1.25,
+        Upgrade::Weapon(kind) => {
+            match weapons
+                .iter_mut()
+                .find(|weapon| weapon.owner == player && weapon.kind == kind)
+            {
+                Some(mut weapon) => weapon.level = (weapon.level + 1).min(MAX_WEAPON_LEVEL),
+                None => {
+                    commands.spawn().insert(Weapon::new(kind, player));
+                }
+            }
+        }
+    }
+
+    pending.0 = pending.0.saturating_sub(1);
+    if pending.0 > 0 {
+        let levels = weapon_levels(player, weapons.iter());
+        offer.0 = pool.roll(&mut rng.upgrades, |upgrade| is_available(upgrade, &levels));
+        selected.0 = 0;
+    } else {
+        let _ = state.pop();
+    }
+}
+
+fn cleanup_offer_ui(mut commands: Commands, ui: Query<Entity, With<LevelUpUi>>) {
+    for e in ui.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
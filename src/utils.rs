@@ -1,37 +1,56 @@
+use std::fs;
+use std::path::PathBuf;
+
 use bevy::prelude::*;
 
 use crate::player::MainCamera;
 
+/// Converts the cursor position to world space through the `MainCamera`,
+/// `None` when the cursor is outside of the window or there is no camera
 pub fn project(
-    windows: Res<Windows>,
-    camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
-) -> Vec2 {
+    windows: &Windows,
+    camera: &Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) -> Option<Vec2> {
     // get the camera info and transform
-    // assuming there is exactly one main camera entity, so query::single() is OK
-    let (camera, camera_transform) = camera.single();
+    // assuming there is at most one main camera entity
+    let (camera, camera_transform) = camera.get_single().ok()?;
 
     // get the window that the camera is displaying to
-    let wnd = windows.get(camera.window).unwrap();
+    let wnd = windows.get(camera.window)?;
 
     // check if the cursor is inside the window and get its position
-    if let Some(screen_pos) = wnd.cursor_position() {
-        // get the size of the window
-        let window_size = Vec2::new(wnd.width() as f32, wnd.height() as f32);
+    let screen_pos = wnd.cursor_position()?;
+
+    // get the size of the window
+    let window_size = Vec2::new(wnd.width() as f32, wnd.height() as f32);
 
-        // convert screen position [0..resolution] to ndc [-1..1] (gpu coordinates)
-        let ndc = (screen_pos / window_size) * 2.0 - Vec2::ONE;
+    // convert screen position [0..resolution] to ndc [-1..1] (gpu coordinates)
+    let ndc = (screen_pos / window_size) * 2.0 - Vec2::ONE;
 
-        // matrix for undoing the projection and camera transform
-        let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix.inverse();
+    // matrix for undoing the projection and camera transform
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix.inverse();
 
-        // use it to convert ndc to world-space coordinates
-        let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0));
+    // use it to convert ndc to world-space coordinates
+    let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0));
 
-        // reduce it to a 2D value
-        let world_pos: Vec2 = world_pos.truncate();
+    // reduce it to a 2D value
+    Some(world_pos.truncate())
+}
 
-        return world_pos;
+/// Despawns every entity with the component `T`, used to clean up when leaving a state
+pub fn despawn_with<T: Component>(mut commands: Commands, q: Query<Entity, With<T>>) {
+    for e in q.iter() {
+        commands.entity(e).despawn_recursive();
     }
+}
 
-    return Vec2::ZERO;
+/// Path of a settings file in the user's config directory, creating the directory if needed
+/// `None` on platforms without a config directory (wasm)
+pub fn config_path(file: &str) -> Option<PathBuf> {
+    let dir = dirs::config_dir()?.join(env!("CARGO_PKG_NAME"));
+    if let Err(error) = fs::create_dir_all(&dir) {
+        error!("Failed to create {}: {}", dir.display(), error);
+        return None;
+    }
+    Some(dir.join(file))
 }
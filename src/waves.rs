@@ -0,0 +1,289 @@
+use std::f32::consts::TAU;
+
+use crate::archetype::{ArchetypeAtlases, EnemyArchetypes};
+use crate::enemy::{spawn_enemy, Enemy};
+use crate::loading::DataAssets;
+use crate::player::Player;
+use crate::pool::Pool;
+use crate::rng::GameRng;
+use crate::timestep::{FixedTimestepAppExt, SimTime};
+use crate::{GameConfiguration, GameState};
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use rand::prelude::*;
+use serde::Deserialize;
+
+pub struct WavePlugin;
+
+/// Timeline of the waves of a run
+/// Loaded from `*.waves.ron` files, see `assets/data/default.waves.ron`
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "0d6f2a43-7f6b-4c52-b1c8-3b8f1f0e5a77"]
+pub struct WaveScript {
+    pub waves: Vec<Wave>,
+    pub difficulty: DifficultyRamp,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Wave {
+    /// Run time in seconds when the wave is spawned for the first time
+    pub at: f32,
+    pub archetype: String,
+    pub count: u32,
+    pub formation: Formation,
+    /// Distance from the player
+    pub radius: f32,
+    #[serde(default)]
+    pub repeat: Option<Repeat>,
+    /// Bosses are not scaled by the difficulty ramp and announce themselves with a `BossArrivalEvent`
+    #[serde(default)]
+    pub boss: bool,
+}
+
+/// The wave is spawned again every `every` seconds, `times` more times
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct Repeat {
+    pub every: f32,
+    pub times: u32,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Formation {
+    /// Random positions on the circle
+    Ring,
+    /// A group around a random point of the circle
+    Cluster { spread: f32 },
+    /// A line tangent to the circle at a random point
+    Line { length: f32 },
+    /// Evenly spaced on the whole circle
+    Surround,
+}
+
+/// Multipliers growing linearly with the run time
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct DifficultyRamp {
+    pub health_per_minute: f32,
+    pub count_per_minute: f32,
+}
+
+impl DifficultyRamp {
+    pub fn health_multiplier(&self, elapsed: f32) -> f32 {
+        1. + self.health_per_minute * elapsed / 60.
+    }
+
+    pub fn count_multiplier(&self, elapsed: f32) -> f32 {
+        1. + self.count_per_minute * elapsed / 60.
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpawnOrder {
+    pub archetype: String,
+    pub count: u32,
+    pub formation: Formation,
+    pub radius: f32,
+    pub health_multiplier: f32,
+    pub boss: bool,
+}
+
+/// Walks through a `WaveScript` as the run time advances
+/// Doesn't touch the ECS, so it can be driven by a simulated clock
+#[derive(Default)]
+pub struct SpawnDirector {
+    pub elapsed: f32,
+    /// Number of times each wave of the script was spawned
+    spawned: Vec<u32>,
+}
+
+impl SpawnDirector {
+    /// Advances the clock by `dt` seconds and returns the waves that are due
+    pub fn advance(&mut self, dt: f32, script: &WaveScript) -> Vec<SpawnOrder> {
+        self.elapsed += dt;
+        self.spawned.resize(script.waves.len(), 0);
+
+        let mut orders = Vec::new();
+        for (wave, spawned) in script.waves.iter().zip(self.spawned.iter_mut()) {
+            let (every, times) = wave
+                .repeat
+                .map_or((0., 0), |repeat| (repeat.every, repeat.times));
+
+            while *spawned <= times && wave.at + *spawned as f32 * every <= self.elapsed {
+                let at = wave.at + *spawned as f32 * every;
+                *spawned += 1;
+
+                let (count, health_multiplier) = if wave.boss {
+                    (wave.count, 1.)
+                } else {
+                    (
+                        (wave.count as f32 * script.difficulty.count_multiplier(at)).round() as u32,
+                        script.difficulty.health_multiplier(at),
+                    )
+                };
+                orders.push(SpawnOrder {
+                    archetype: wave.archetype.clone(),
+                    count,
+                    formation: wave.formation,
+                    radius: wave.radius,
+                    health_multiplier,
+                    boss: wave.boss,
+                });
+            }
+        }
+
+        orders
+    }
+}
+
+/// Positions of `count` enemies in `formation` on a circle of `radius` around `center`
+pub fn formation_positions(
+    formation: Formation,
+    count: u32,
+    radius: f32,
+    center: Vec2,
+    rng: &mut impl Rng,
+) -> Vec<Vec2> {
+    let on_circle = |t: f32| center + Vec2::new(t.cos(), t.sin()) * radius;
+
+    match formation {
+        Formation::Ring => (0..count)
+            .map(|_| on_circle(rng.gen_range(0f32..TAU)))
+            .collect(),
+        Formation::Cluster { spread } => {
+            let p = on_circle(rng.gen_range(0f32..TAU));
+            (0..count)
+                .map(|_| {
+                    let dx = rng.gen_range((-spread / 2.)..=(spread / 2.));
+                    let dy = rng.gen_range((-spread / 2.)..=(spread / 2.));
+                    p + Vec2::new(dx, dy)
+                })
+                .collect()
+        }
+        Formation::Line { length } => {
+            let t = rng.gen_range(0f32..TAU);
+            let p = on_circle(t);
+            let tangent = Vec2::new(-t.sin(), t.cos());
+            (0..count)
+                .map(|i| {
+                    let offset = if count > 1 {
+                        i as f32 / (count - 1) as f32 - 0.5
+                    } else {
+                        0.
+                    };
+                    p + tangent * offset * length
+                })
+                .collect()
+        }
+        Formation::Surround => (0..count)
+            .map(|i| on_circle(i as f32 * TAU / count as f32))
+            .collect(),
+    }
+}
+
+/// Marks enemies spawned by a boss wave
+#[derive(Component)]
+pub struct Boss;
+
+pub struct BossArrivalEvent {
+    pub entity: Entity,
+}
+
+#[derive(Default)]
+pub struct WaveScriptLoader;
+
+impl AssetLoader for WaveScriptLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let script: WaveScript = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(script));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["waves.ron"]
+    }
+}
+
+/// This plugin spawns the enemies following the wave script of `DataAssets`
+/// Spawning is only active during the State `GameState::Playing`
+impl Plugin for WavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<WaveScript>()
+            .init_asset_loader::<WaveScriptLoader>()
+            .init_resource::<SpawnDirector>()
+            .add_fixed_event::<BossArrivalEvent>()
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(reset_director))
+            .add_fixed_system_set(SystemSet::new().with_system(run_director));
+    }
+}
+
+fn reset_director(mut director: ResMut<SpawnDirector>) {
+    *director = SpawnDirector::default();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_director(
+    mut commands: Commands,
+    time: Res<SimTime>,
+    mut director: ResMut<SpawnDirector>,
+    data: Res<DataAssets>,
+    scripts: Res<Assets<WaveScript>>,
+    archetypes: Res<Assets<EnemyArchetypes>>,
+    asset_server: Res<AssetServer>,
+    mut atlases: ResMut<ArchetypeAtlases>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut pool: ResMut<Pool<Enemy>>,
+    mut event_boss: EventWriter<BossArrivalEvent>,
+    mut rng: ResMut<GameRng>,
+    player: Query<&Transform, With<Player>>,
+    config: Res<GameConfiguration>,
+) {
+    let (script, archetypes) = match (scripts.get(&data.waves), archetypes.get(&data.enemies)) {
+        (Some(script), Some(archetypes)) => (script, archetypes),
+        _ => return,
+    };
+    // the simulated position, the camera follows the interpolated one and shakes
+    let center = player.single().translation.xy();
+
+    for order in director.advance(time.delta_seconds(), script) {
+        let archetype = match archetypes.0.get(&order.archetype) {
+            Some(archetype) => archetype,
+            None => {
+                warn!("Unknown enemy archetype '{}'", order.archetype);
+                continue;
+            }
+        };
+        let atlas = atlases.get_or_load(&archetype.atlas, &asset_server, &mut texture_atlases);
+
+        for p in formation_positions(
+            order.formation,
+            order.count,
+            order.radius,
+            center,
+            &mut rng.spawning,
+        ) {
+            let enemy = spawn_enemy(
+                &mut commands,
+                &mut pool,
+                &order.archetype,
+                archetype,
+                atlas.clone(),
+                p,
+                config.scale,
+                order.health_multiplier,
+            );
+
+            if order.boss {
+                commands.entity(enemy).insert(Boss);
+                event_boss.send(BossArrivalEvent { entity: enemy });
+            }
+        }
+    }
+}
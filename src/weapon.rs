@@ -0,0 +1,625 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+use crate::animation::AnimationLibrary;
+use crate::enemy::{sprite_z, Enemy};
+use crate::loading::TextureAssets;
+use crate::player::{Aim, DamageKind, DealDamageEvent, Facing, HitBox, VFX};
+use crate::pool::Pool;
+use crate::projectile::{Projectile, ProjectileTarget};
+use crate::spatial::{SpatialEntry, SpatialHash, UpdateSpatialHash};
+use crate::timestep::{FixedTimestepAppExt, Interpolated, SimTime};
+use crate::utils::despawn_with;
+use crate::{GameConfiguration, GameState};
+use benimator::{Play, SpriteSheetAnimation};
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+
+pub struct WeaponPlugin;
+
+pub const MAX_WEAPON_LEVEL: u32 = 8;
+
+/// Aimed bolts strike enemies in this range and within this angle of the aim
+const BOLT_AIM_RANGE: f32 = 400.;
+const BOLT_AIM_ANGLE: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WeaponKind {
+    /// Strikes the nearest enemies, or the nearest ones in the aimed direction
+    Bolt,
+    /// Blades orbiting around the owner
+    Blades,
+    /// Hurts every enemy around the owner
+    Aura,
+    /// Hits a rectangle in front of the owner, or on the aimed side
+    Whip,
+    /// Thrown in the direction the owner aims at, or else is facing
+    Knife,
+}
+
+/// * `damage` is dealt per hit
+/// * `cooldown` is the time in seconds between two attacks (or damage ticks for `Blades`)
+/// * `area` is a radius, a length or a sprite scale depending on the weapon
+/// * `amount` is the number of targets, blades, whip strikes or knives
+/// * `speed` is in radians per second for `Blades` and units per second for `Knife`
+/// * `duration` is the lifetime of thrown knives
+/// * `pierce` is the number of enemies a thrown knife goes through
+#[derive(Clone, Copy, Debug)]
+pub struct WeaponStats {
+    pub damage: f32,
+    pub cooldown: f32,
+    pub area: f32,
+    pub amount: u32,
+    pub speed: f32,
+    pub duration: f32,
+    pub pierce: u32,
+}
+
+impl WeaponKind {
+    pub const ALL: [WeaponKind; 5] = [
+        WeaponKind::Bolt,
+        WeaponKind::Blades,
+        WeaponKind::Aura,
+        WeaponKind::Whip,
+        WeaponKind::Knife,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WeaponKind::Bolt => "Magic bolt",
+            WeaponKind::Blades => "Orbiting blades",
+            WeaponKind::Aura => "Holy aura",
+            WeaponKind::Whip => "Whip",
+            WeaponKind::Knife => "Throwing knife",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            WeaponKind::Bolt => "Strikes the nearest enemies",
+            WeaponKind::Blades => "Blades circle around you",
+            WeaponKind::Aura => "Damages every enemy close to you",
+            WeaponKind::Whip => "Lashes out in front of you",
+            WeaponKind::Knife => "Thrown where you aim",
+        }
+    }
+
+    fn base_stats(&self) -> WeaponStats {
+        match self {
+            WeaponKind::Bolt => WeaponStats {
+                damage: 1.,
+                cooldown: 2.,
+                area: 1.,
+                amount: 1,
+                speed: 0.,
+                duration: 0.,
+                pierce: 0,
+            },
+            WeaponKind::Blades => WeaponStats {
+                damage: 1.,
+                cooldown: 0.5,
+                area: 60.,
+                amount: 2,
+                speed: 3.,
+                duration: 0.,
+                pierce: 0,
+            },
+            WeaponKind::Aura => WeaponStats {
+                damage: 0.5,
+                cooldown: 1.,
+                area: 70.,
+                amount: 1,
+                speed: 0.,
+                duration: 0.,
+                pierce: 0,
+            },
+            WeaponKind::Whip => WeaponStats {
+                damage: 2.,
+                cooldown: 1.5,
+                area: 90.,
+                amount: 1,
+                speed: 0.,
+                duration: 0.,
+                pierce: 0,
+            },
+            WeaponKind::Knife => WeaponStats {
+                damage: 1.,
+                cooldown: 1.,
+                area: 1.,
+                amount: 1,
+                speed: 400.,
+                duration: 1.5,
+                pierce: 0,
+            },
+        }
+    }
+
+    /// Every level past the first adds 25% damage, 10% area and 5% less cooldown,
+    /// every second level adds one to `amount` and every third level one to `pierce`
+    pub fn stats(&self, level: u32) -> WeaponStats {
+        let base = self.base_stats();
+        let bonus = level.clamp(1, MAX_WEAPON_LEVEL) - 1;
+
+        WeaponStats {
+            damage: base.damage * (1. + 0.25 * bonus as f32),
+            cooldown: base.cooldown * 0.95f32.powi(bonus as i32),
+            area: base.area * (1. + 0.1 * bonus as f32),
+            amount: base.amount + bonus / 2,
+            speed: base.speed,
+            duration: base.duration,
+            pierce: base.pierce + bonus / 3,
+        }
+    }
+
+    /// Name of the effect in the `AnimationLibrary`, see `assets/data/default.animations.ron`
+    fn vfx_animation(&self) -> &'static str {
+        match self {
+            WeaponKind::Bolt => "magic_bolt",
+            WeaponKind::Blades => "magic_blades",
+            WeaponKind::Aura => "magic_aura",
+            WeaponKind::Whip => "magic_whip",
+            WeaponKind::Knife => "magic_knife",
+        }
+    }
+
+    /// Still frame of the magic atlas for the weapons that fly around as sprites
+    fn sprite_frame(&self) -> usize {
+        match self {
+            WeaponKind::Bolt => 136,
+            WeaponKind::Blades => 24,
+            WeaponKind::Aura => 96,
+            WeaponKind::Whip => 48,
+            WeaponKind::Knife => 72,
+        }
+    }
+}
+
+/// Modifiers applied on top of the stats of every weapon owned by an entity
+#[derive(Component)]
+pub struct WeaponModifiers {
+    pub damage: f32,
+    pub cooldown: f32,
+    pub area: f32,
+}
+
+impl Default for WeaponModifiers {
+    fn default() -> Self {
+        WeaponModifiers {
+            damage: 1.,
+            cooldown: 1.,
+            area: 1.,
+        }
+    }
+}
+
+/// A weapon is its own entity, an owner can have several of them
+#[derive(Component)]
+pub struct Weapon {
+    pub kind: WeaponKind,
+    pub level: u32,
+    pub owner: Entity,
+    cooldown: Timer,
+}
+
+impl Weapon {
+    pub fn new(kind: WeaponKind, owner: Entity) -> Self {
+        Weapon {
+            kind,
+            level: 1,
+            owner,
+            cooldown: Timer::from_seconds(kind.stats(1).cooldown, true),
+        }
+    }
+
+    pub fn stats(&self, modifiers: Option<&WeaponModifiers>) -> WeaponStats {
+        let mut stats = self.kind.stats(self.level);
+        if let Some(modifiers) = modifiers {
+            stats.damage *= modifiers.damage;
+            stats.cooldown *= modifiers.cooldown;
+            stats.area *= modifiers.area;
+        }
+        stats
+    }
+
+    fn fired(&self, kind: WeaponKind) -> bool {
+        self.kind == kind && self.cooldown.just_finished()
+    }
+}
+
+#[derive(Component)]
+struct OrbitBlade {
+    weapon: Entity,
+    angle: f32,
+}
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct TickWeapons;
+
+/// This plugin fires the weapons equipped by the player
+/// Weapon logic is only active during the State `GameState::Playing`
+impl Plugin for WeaponPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_fixed_system_set(
+            SystemSet::new()
+                .with_system(tick_weapons.label(TickWeapons))
+                .with_system(fire_bolts.after(TickWeapons).after(UpdateSpatialHash))
+                .with_system(fire_aura.after(TickWeapons).after(UpdateSpatialHash))
+                .with_system(fire_whip.after(TickWeapons).after(UpdateSpatialHash))
+                .with_system(throw_knives.after(TickWeapons))
+                .with_system(strike_blades.after(TickWeapons).after(UpdateSpatialHash))
+                .with_system(sync_blades)
+                .with_system(orbit_blades),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Playing)
+                .with_system(despawn_with::<Weapon>)
+                .with_system(despawn_with::<OrbitBlade>),
+        );
+    }
+}
+
+fn spawn_vfx(
+    commands: &mut Commands,
+    pool: &mut Pool<VFX>,
+    textures: &TextureAssets,
+    animation: Handle<SpriteSheetAnimation>,
+    transform: Transform,
+    flip_x: bool,
+) {
+    pool.spawn(commands)
+        .insert_bundle(SpriteSheetBundle {
+            texture_atlas: textures.magic.clone(),
+            transform,
+            sprite: TextureAtlasSprite {
+                flip_x,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(animation)
+        .insert(Play)
+        .insert(VFX);
+}
+
+fn tick_weapons(
+    time: Res<SimTime>,
+    mut weapons: Query<&mut Weapon>,
+    modifiers: Query<&WeaponModifiers>,
+) {
+    for mut weapon in weapons.iter_mut() {
+        let stats = weapon.stats(modifiers.get(weapon.owner).ok());
+        let cooldown = Duration::from_secs_f32(stats.cooldown.max(0.05));
+        if weapon.cooldown.duration() != cooldown {
+            weapon.cooldown.set_duration(cooldown);
+        }
+        weapon.cooldown.tick(time.delta());
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn fire_bolts(
+    mut commands: Commands,
+    weapons: Query<&Weapon>,
+    owners: Query<(&Transform, Option<&WeaponModifiers>, Option<&Aim>)>,
+    enemy_hash: Res<SpatialHash<Enemy>>,
+    mut event_deal_damage: EventWriter<DealDamageEvent>,
+    animations: Res<AnimationLibrary>,
+    textures: Res<TextureAssets>,
+    mut vfx_pool: ResMut<Pool<VFX>>,
+    config: Res<GameConfiguration>,
+) {
+    for weapon in weapons.iter().filter(|w| w.fired(WeaponKind::Bolt)) {
+        let (owner, modifiers, aim) = match owners.get(weapon.owner) {
+            Ok(owner) => owner,
+            Err(_) => continue,
+        };
+        let stats = weapon.stats(modifiers);
+        let p = owner.translation.xy();
+
+        let mut targets = match aim.and_then(|aim| aim.0) {
+            Some(aim) => aimed_targets(&enemy_hash, p, aim, stats.amount as usize),
+            None => Vec::new(),
+        };
+        // nothing in the aimed direction, strike the nearest enemies anyway
+        if targets.is_empty() {
+            targets = enemy_hash.nearest(p, stats.amount as usize);
+        }
+
+        let animation_handle = animations.get(weapon.kind.vfx_animation());
+        for (target, _distance) in targets {
+            let p = target.pos;
+            spawn_vfx(
+                &mut commands,
+                &mut vfx_pool,
+                &textures,
+                animation_handle.clone(),
+                Transform::from_translation(p.extend(sprite_z(p) + 0.1))
+                    .with_scale(Vec3::splat(config.scale * stats.area)),
+                false,
+            );
+            event_deal_damage.send(DealDamageEvent {
+                entity: target.entity,
+                source: weapon.owner,
+                amount: stats.damage,
+                kind: DamageKind::Magic,
+            });
+        }
+    }
+}
+
+/// The `k` nearest enemies within `BOLT_AIM_ANGLE` of `aim`
+fn aimed_targets(
+    enemy_hash: &SpatialHash<Enemy>,
+    p: Vec2,
+    aim: Vec2,
+    k: usize,
+) -> Vec<(SpatialEntry, f32)> {
+    let mut targets: Vec<_> = enemy_hash
+        .within_radius(p, BOLT_AIM_RANGE)
+        .filter(|entry| entry.pos != p && aim.angle_between(entry.pos - p).abs() <= BOLT_AIM_ANGLE)
+        .map(|entry| (*entry, (entry.pos - p).length()))
+        .collect();
+    targets.sort_by(|a, b| a.1.total_cmp(&b.1));
+    targets.truncate(k);
+    targets
+}
+
+#[allow(clippy::too_many_arguments)]
+fn fire_aura(
+    mut commands: Commands,
+    weapons: Query<&Weapon>,
+    owners: Query<(&Transform, Option<&WeaponModifiers>)>,
+    enemy_hash: Res<SpatialHash<Enemy>>,
+    mut event_deal_damage: EventWriter<DealDamageEvent>,
+    animations: Res<AnimationLibrary>,
+    textures: Res<TextureAssets>,
+    mut vfx_pool: ResMut<Pool<VFX>>,
+) {
+    for weapon in weapons.iter().filter(|w| w.fired(WeaponKind::Aura)) {
+        let (owner, modifiers) = match owners.get(weapon.owner) {
+            Ok(owner) => owner,
+            Err(_) => continue,
+        };
+        let stats = weapon.stats(modifiers);
+        let center = owner.translation.xy();
+
+        // the magic sprites are 24 pixels wide
+        spawn_vfx(
+            &mut commands,
+            &mut vfx_pool,
+            &textures,
+            animations.get(weapon.kind.vfx_animation()),
+            Transform::from_translation(center.extend(sprite_z(center) - 0.1))
+                .with_scale(Vec3::splat(stats.area * 2. / 24.)),
+            false,
+        );
+
+        for target in enemy_hash.within_radius(center, stats.area) {
+            event_deal_damage.send(DealDamageEvent {
+                entity: target.entity,
+                source: weapon.owner,
+                amount: stats.damage,
+                kind: DamageKind::Magic,
+            });
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn fire_whip(
+    mut commands: Commands,
+    weapons: Query<&Weapon>,
+    owners: Query<(
+        &Transform,
+        Option<&WeaponModifiers>,
+        Option<&Facing>,
+        Option<&Aim>,
+    )>,
+    enemy_hash: Res<SpatialHash<Enemy>>,
+    mut event_deal_damage: EventWriter<DealDamageEvent>,
+    animations: Res<AnimationLibrary>,
+    textures: Res<TextureAssets>,
+    mut vfx_pool: ResMut<Pool<VFX>>,
+    config: Res<GameConfiguration>,
+) {
+    for weapon in weapons.iter().filter(|w| w.fired(WeaponKind::Whip)) {
+        let (owner, modifiers, facing, aim) = match owners.get(weapon.owner) {
+            Ok(owner) => owner,
+            Err(_) => continue,
+        };
+        let stats = weapon.stats(modifiers);
+        let forward = aim
+            .and_then(|aim| aim.0)
+            .or_else(|| facing.map(|facing| facing.0));
+        let front = match forward {
+            Some(forward) if forward.x < 0. => -1.,
+            _ => 1.,
+        };
+        let animation_handle = animations.get(weapon.kind.vfx_animation());
+
+        // strikes alternate between the front and the back of the owner
+        for i in 0..stats.amount {
+            let side = if i % 2 == 0 { front } else { -front };
+            let center = owner.translation.xy() + Vec2::new(side * stats.area / 2., 0.);
+            let size = Vec2::new(stats.area, 16. * config.scale);
+
+            spawn_vfx(
+                &mut commands,
+                &mut vfx_pool,
+                &textures,
+                animation_handle.clone(),
+                Transform::from_translation(center.extend(sprite_z(center) + 0.1))
+                    .with_scale(Vec3::new(stats.area / 24., config.scale, 1.)),
+                side < 0.,
+            );
+
+            for target in enemy_hash.overlapping(center, size) {
+                event_deal_damage.send(DealDamageEvent {
+                    entity: target.entity,
+                    source: weapon.owner,
+                    amount: stats.damage,
+                    kind: DamageKind::Physical,
+                });
+            }
+        }
+    }
+}
+
+fn throw_knives(
+    mut commands: Commands,
+    weapons: Query<&Weapon>,
+    owners: Query<(
+        &Transform,
+        Option<&WeaponModifiers>,
+        Option<&Facing>,
+        Option<&Aim>,
+    )>,
+    textures: Res<TextureAssets>,
+    config: Res<GameConfiguration>,
+) {
+    for weapon in weapons.iter().filter(|w| w.fired(WeaponKind::Knife)) {
+        let (owner, modifiers, facing, aim) = match owners.get(weapon.owner) {
+            Ok(owner) => owner,
+            Err(_) => continue,
+        };
+        let stats = weapon.stats(modifiers);
+        let direction = aim
+            .and_then(|aim| aim.0)
+            .unwrap_or_else(|| facing.map_or(Vec2::X, |facing| facing.0));
+        let p = owner.translation.xy();
+
+        // several knives fan out 10 degrees apart
+        for i in 0..stats.amount {
+            let spread = (i as f32 - (stats.amount - 1) as f32 / 2.) * 10f32.to_radians();
+            let (sin, cos) = spread.sin_cos();
+            let direction = Vec2::new(
+                direction.x * cos - direction.y * sin,
+                direction.x * sin + direction.y * cos,
+            );
+
+            commands
+                .spawn_bundle(SpriteSheetBundle {
+                    texture_atlas: textures.magic.clone(),
+                    transform: Transform::from_translation(p.extend(sprite_z(p) + 0.1))
+                        .with_scale(Vec3::splat(config.scale * stats.area / 2.)),
+                    sprite: TextureAtlasSprite::new(weapon.kind.sprite_frame()),
+                    ..Default::default()
+                })
+                .insert(HitBox {
+                    pos: Vec2::ZERO,
+                    size: Vec2::splat(8. * config.scale),
+                    damage: stats.damage,
+                })
+                .insert(Interpolated::default())
+                .insert(Projectile::new(
+                    weapon.owner,
+                    direction * stats.speed,
+                    stats.duration,
+                    stats.pierce,
+                    DamageKind::Physical,
+                    ProjectileTarget::Enemies,
+                ));
+        }
+    }
+}
+
+/// Keeps one `OrbitBlade` entity per `amount` of every `Blades` weapon
+fn sync_blades(
+    mut commands: Commands,
+    weapons: Query<(Entity, &Weapon)>,
+    modifiers: Query<&WeaponModifiers>,
+    blades: Query<(Entity, &OrbitBlade)>,
+    textures: Res<TextureAssets>,
+    config: Res<GameConfiguration>,
+) {
+    let mut counts = HashMap::new();
+    for (_, blade) in blades.iter() {
+        *counts.entry(blade.weapon).or_insert(0) += 1;
+    }
+
+    for (weapon_entity, weapon) in weapons.iter() {
+        if weapon.kind != WeaponKind::Blades {
+            continue;
+        }
+        let stats = weapon.stats(modifiers.get(weapon.owner).ok());
+        if counts.get(&weapon_entity).copied().unwrap_or(0) == stats.amount {
+            continue;
+        }
+
+        for (e, blade) in blades.iter() {
+            if blade.weapon == weapon_entity {
+                commands.entity(e).despawn_recursive();
+            }
+        }
+        for i in 0..stats.amount {
+            commands
+                .spawn_bundle(SpriteSheetBundle {
+                    texture_atlas: textures.magic.clone(),
+                    transform: Transform::from_scale(Vec3::splat(config.scale / 2.)),
+                    sprite: TextureAtlasSprite::new(weapon.kind.sprite_frame()),
+                    ..Default::default()
+                })
+                .insert(Interpolated::default())
+                .insert(OrbitBlade {
+                    weapon: weapon_entity,
+                    angle: i as f32 * TAU / stats.amount as f32,
+                });
+        }
+    }
+}
+
+fn orbit_blades(
+    time: Res<SimTime>,
+    weapons: Query<&Weapon>,
+    owners: Query<(&Transform, Option<&WeaponModifiers>), Without<OrbitBlade>>,
+    mut blades: Query<(&mut Transform, &mut OrbitBlade)>,
+) {
+    for (mut transform, mut blade) in blades.iter_mut() {
+        let weapon = match weapons.get(blade.weapon) {
+            Ok(weapon) => weapon,
+            Err(_) => continue,
+        };
+        let (owner, modifiers) = match owners.get(weapon.owner) {
+            Ok(owner) => owner,
+            Err(_) => continue,
+        };
+        let stats = weapon.stats(modifiers);
+
+        blade.angle = (blade.angle + stats.speed * time.delta_seconds()) % TAU;
+        let p =
+            owner.translation.xy() + Vec2::new(blade.angle.cos(), blade.angle.sin()) * stats.area;
+        transform.translation = p.extend(sprite_z(p) + 0.1);
+    }
+}
+
+fn strike_blades(
+    weapons: Query<(Entity, &Weapon)>,
+    modifiers: Query<&WeaponModifiers>,
+    blades: Query<(&Transform, &OrbitBlade)>,
+    enemy_hash: Res<SpatialHash<Enemy>>,
+    mut event_deal_damage: EventWriter<DealDamageEvent>,
+    config: Res<GameConfiguration>,
+) {
+    for (weapon_entity, weapon) in weapons.iter() {
+        if !weapon.fired(WeaponKind::Blades) {
+            continue;
+        }
+        let stats = weapon.stats(modifiers.get(weapon.owner).ok());
+        let size = Vec2::splat(12. * config.scale);
+
+        for (transform, blade) in blades.iter() {
+            if blade.weapon != weapon_entity {
+                continue;
+            }
+            for target in enemy_hash.overlapping(transform.translation.xy(), size) {
+                event_deal_damage.send(DealDamageEvent {
+                    entity: target.entity,
+                    source: weapon.owner,
+                    amount: stats.damage,
+                    kind: DamageKind::Physical,
+                });
+            }
+        }
+    }
+}
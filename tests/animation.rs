@@ -0,0 +1,55 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_game::animation::{CharacterAnimator, CharacterState};
+use common::Harness;
+
+#[test]
+fn character_states_follow_their_priority() {
+    let mut animator = CharacterAnimator::new("necromancer");
+    let all = |_| true;
+    assert_eq!(animator.next_state(all), CharacterState::Idle);
+
+    animator.moving = true;
+    assert_eq!(animator.next_state(all), CharacterState::Walk);
+    animator.attacking = 0.2;
+    assert_eq!(animator.next_state(all), CharacterState::Attack);
+    // states without an animation fall back to the next one
+    assert_eq!(
+        animator.next_state(|state| state != CharacterState::Attack),
+        CharacterState::Walk
+    );
+
+    animator.hurt = true;
+    assert_eq!(animator.next_state(all), CharacterState::Hurt);
+    animator.dead = true;
+    assert_eq!(animator.next_state(|_| false), CharacterState::Death);
+}
+
+#[test]
+fn the_player_walks_and_the_dead_fall() {
+    let mut harness = Harness::new();
+    harness.disarm();
+    let player = harness.player();
+    let enemy = harness.spawn_enemy("necromancer", Vec2::new(300., 0.));
+    let state = |harness: &Harness, entity| {
+        harness
+            .app
+            .world
+            .get::<CharacterAnimator>(entity)
+            .unwrap()
+            .state()
+    };
+
+    harness.step(2);
+    assert_eq!(state(&harness, player), Some(CharacterState::Idle));
+    assert_eq!(state(&harness, enemy), Some(CharacterState::Walk));
+
+    harness.set_movement(Some(Vec2::X));
+    harness.step(2);
+    assert_eq!(state(&harness, player), Some(CharacterState::Walk));
+
+    harness.damage(enemy, 100.);
+    harness.step(2);
+    assert_eq!(state(&harness, enemy), Some(CharacterState::Death));
+}
@@ -0,0 +1,316 @@
+//! Headless simulation of the game logic for integration tests
+//! No window, renderer or audio device is needed: the `GameLogicPlugin` runs on `MinimalPlugins`,
+//! asset collections are stubbed and every `update` simulates exactly one tick
+
+#![allow(dead_code)]
+
+use bevy::asset::AssetPlugin;
+use bevy::ecs::system::CommandQueue;
+use bevy::input::gamepad::GamepadEventRaw;
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::{ElementState, InputPlugin};
+use bevy::prelude::*;
+use bevy::transform::TransformPlugin;
+use bevy_game::actions::{Actions, ActionsPlugin};
+use bevy_game::animation::AnimationDefs;
+use bevy_game::archetype::EnemyArchetypes;
+use bevy_game::enemy::{spawn_enemy, Enemy};
+use bevy_game::loading::{DataAssets, FontAssets, TextureAssets};
+use bevy_game::player::{DamageKind, DealDamageEvent, Health, Player};
+use bevy_game::pool::Pool;
+use bevy_game::rng::RngSeed;
+use bevy_game::timestep::{SimMode, SimTime, TICKS_PER_SECOND};
+use bevy_game::waves::{DifficultyRamp, Wave, WaveScript};
+use bevy_game::weapon::{Weapon, WeaponKind};
+use bevy_game::{GameLogicPlugin, GameState};
+
+pub const SEED: u64 = 42;
+
+pub struct Harness {
+    pub app: App,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Harness::new()
+    }
+}
+
+impl Harness {
+    /// A run without any wave, enemies are spawned by the test
+    pub fn new() -> Self {
+        Harness::with_waves(Vec::new())
+    }
+
+    /// A run following `waves`, with the archetypes of `assets/data/archetypes.enemies.ron`
+    pub fn with_waves(waves: Vec<Wave>) -> Self {
+        Harness::build(waves, false)
+    }
+
+    /// A run without any wave where `Actions` come from the input devices,
+    /// fed with synthetic events instead of `set_movement`
+    pub fn with_input() -> Self {
+        Harness::build(Vec::new(), true)
+    }
+
+    fn build(waves: Vec<Wave>, input: bool) -> Self {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(TransformPlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(InputPlugin)
+            .add_asset::<TextureAtlas>()
+            .insert_resource(RngSeed(Some(SEED)))
+            .add_plugin(GameLogicPlugin);
+        if input {
+            app.add_plugin(ActionsPlugin);
+        }
+
+        let archetypes: EnemyArchetypes =
+            ron::de::from_str(include_str!("../../assets/data/archetypes.enemies.ron"))
+                .expect("invalid enemy archetypes");
+        let enemies = app
+            .world
+            .get_resource_mut::<Assets<EnemyArchetypes>>()
+            .unwrap()
+            .add(archetypes);
+        let waves = app
+            .world
+            .get_resource_mut::<Assets<WaveScript>>()
+            .unwrap()
+            .add(WaveScript {
+                waves,
+                difficulty: DifficultyRamp {
+                    health_per_minute: 0.,
+                    count_per_minute: 0.,
+                },
+            });
+
+        let animations: AnimationDefs =
+            ron::de::from_str(include_str!("../../assets/data/default.animations.ron"))
+                .expect("invalid animations");
+        let animations = app
+            .world
+            .get_resource_mut::<Assets<AnimationDefs>>()
+            .unwrap()
+            .add(animations);
+
+        app.insert_resource(DataAssets {
+            enemies,
+            waves,
+            animations,
+        })
+        .insert_resource(TextureAssets {
+            misc: Handle::default(),
+            necromancer: Handle::default(),
+            castle: Handle::default(),
+            magic: Handle::default(),
+        })
+        .insert_resource(FontAssets {
+            fira_sans: Handle::default(),
+        });
+        app.world.get_resource_mut::<SimTime>().unwrap().mode = SimMode::Manual(1);
+        app.world
+            .get_resource_mut::<State<GameState>>()
+            .unwrap()
+            .set(GameState::Playing)
+            .unwrap();
+
+        // enters GameState::Playing, spawning the player
+        app.update();
+
+        Harness { app }
+    }
+
+    pub fn step(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.app.update();
+        }
+    }
+
+    pub fn step_seconds(&mut self, seconds: f32) {
+        self.step((seconds * TICKS_PER_SECOND as f32).round() as u32);
+    }
+
+    /// Steps one tick at a time until `condition` holds, returns the elapsed seconds
+    /// or `None` if it still doesn't hold after `max_seconds`
+    pub fn step_until(
+        &mut self,
+        max_seconds: f32,
+        mut condition: impl FnMut(&mut Harness) -> bool,
+    ) -> Option<f32> {
+        let max_ticks = (max_seconds * TICKS_PER_SECOND as f32).round() as u32;
+        for tick in 1..=max_ticks {
+            self.app.update();
+            if condition(self) {
+                return Some(tick as f32 / TICKS_PER_SECOND as f32);
+            }
+        }
+        None
+    }
+
+    pub fn state(&self) -> GameState {
+        self.app
+            .world
+            .get_resource::<State<GameState>>()
+            .unwrap()
+            .current()
+            .clone()
+    }
+
+    pub fn set_movement(&mut self, movement: Option<Vec2>) {
+        self.app
+            .world
+            .get_resource_mut::<Actions>()
+            .unwrap()
+            .player_movement = movement;
+    }
+
+    pub fn set_aim(&mut self, aim: Option<Vec2>) {
+        self.app
+            .world
+            .get_resource_mut::<Actions>()
+            .unwrap()
+            .aim_direction = aim;
+    }
+
+    /// Presses `key` and releases it after one frame
+    pub fn tap_key(&mut self, key: KeyCode) {
+        for state in [ElementState::Pressed, ElementState::Released] {
+            self.app
+                .world
+                .get_resource_mut::<Events<KeyboardInput>>()
+                .unwrap()
+                .send(KeyboardInput {
+                    scan_code: 0,
+                    key_code: Some(key),
+                    state,
+                });
+            self.app.update();
+        }
+    }
+
+    /// Sends a raw event of `gamepad`, as the gamepad backend would
+    pub fn gamepad_event(&mut self, gamepad: usize, event: GamepadEventType) {
+        self.app
+            .world
+            .get_resource_mut::<Events<GamepadEventRaw>>()
+            .unwrap()
+            .send(GamepadEventRaw(Gamepad(gamepad), event));
+    }
+
+    pub fn movement(&self) -> Option<Vec2> {
+        self.app
+            .world
+            .get_resource::<Actions>()
+            .unwrap()
+            .player_movement
+    }
+
+    pub fn aim(&self) -> Option<Vec2> {
+        self.app
+            .world
+            .get_resource::<Actions>()
+            .unwrap()
+            .aim_direction
+    }
+
+    pub fn player(&mut self) -> Entity {
+        self.app
+            .world
+            .query_filtered::<Entity, With<Player>>()
+            .iter(&self.app.world)
+            .next()
+            .expect("no player")
+    }
+
+    pub fn position(&self, entity: Entity) -> Vec2 {
+        self.app
+            .world
+            .get::<Transform>(entity)
+            .unwrap()
+            .translation
+            .truncate()
+    }
+
+    pub fn health(&self, entity: Entity) -> f32 {
+        self.app.world.get::<Health>(entity).unwrap().0
+    }
+
+    pub fn has<T: Component>(&self, entity: Entity) -> bool {
+        self.app.world.get::<T>(entity).is_some()
+    }
+
+    /// Despawns the weapons of the player, so that only the test deals damage
+    pub fn disarm(&mut self) {
+        let weapons: Vec<Entity> = self
+            .app
+            .world
+            .query_filtered::<Entity, With<Weapon>>()
+            .iter(&self.app.world)
+            .collect();
+        for weapon in weapons {
+            self.app.world.despawn(weapon);
+        }
+    }
+
+    /// Gives the player a new weapon of `kind`
+    pub fn equip(&mut self, kind: WeaponKind) {
+        let player = self.player();
+        self.app.world.spawn().insert(Weapon::new(kind, player));
+    }
+
+    pub fn spawn_enemy(&mut self, archetype: &str, p: Vec2) -> Entity {
+        let handle = self
+            .app
+            .world
+            .get_resource::<DataAssets>()
+            .unwrap()
+            .enemies
+            .clone();
+        let archetype_def = self
+            .app
+            .world
+            .get_resource::<Assets<EnemyArchetypes>>()
+            .unwrap()
+            .get(handle)
+            .unwrap()
+            .0
+            .get(archetype)
+            .unwrap_or_else(|| panic!("unknown archetype '{}'", archetype))
+            .clone();
+
+        let mut pool = self.app.world.remove_resource::<Pool<Enemy>>().unwrap();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &self.app.world);
+        let enemy = spawn_enemy(
+            &mut commands,
+            &mut pool,
+            archetype,
+            &archetype_def,
+            Handle::default(),
+            p,
+            3.,
+            1.,
+        );
+        queue.apply(&mut self.app.world);
+        self.app.world.insert_resource(pool);
+
+        enemy
+    }
+
+    /// Hits `entity` once, as if the player did it
+    pub fn damage(&mut self, entity: Entity, amount: f32) {
+        let source = self.player();
+        self.app
+            .world
+            .get_resource_mut::<Events<DealDamageEvent>>()
+            .unwrap()
+            .send(DealDamageEvent {
+                entity,
+                source,
+                amount,
+                kind: DamageKind::Physical,
+            });
+    }
+}
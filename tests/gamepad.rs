@@ -0,0 +1,124 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_game::input_map::{AimMode, InputMap, StickSettings};
+use common::Harness;
+
+fn left_stick(harness: &mut Harness, gamepad: usize, x: f32, y: f32) {
+    for (axis, value) in [
+        (GamepadAxisType::LeftStickX, x),
+        (GamepadAxisType::LeftStickY, y),
+    ] {
+        harness.gamepad_event(gamepad, GamepadEventType::AxisChanged(axis, value));
+    }
+}
+
+fn connected() -> Harness {
+    let mut harness = Harness::with_input();
+    harness.gamepad_event(0, GamepadEventType::Connected);
+    harness.step(1);
+    harness
+}
+
+#[test]
+fn stick_response_curve() {
+    let stick = StickSettings {
+        deadzone: 0.2,
+        outer_deadzone: 0.9,
+        exponent: 2.,
+    };
+
+    assert_eq!(stick.apply(Vec2::new(0.1, -0.1)), None);
+    let full = stick.apply(Vec2::new(0., 0.95)).unwrap();
+    assert!((full - Vec2::Y).length() < 1e-5, "{:?}", full);
+    let half = stick.apply(Vec2::new(-0.55, 0.)).unwrap();
+    assert!((half - Vec2::new(-0.25, 0.)).length() < 1e-5, "{:?}", half);
+}
+
+#[test]
+fn half_tilted_stick_walks_slowly() {
+    let mut harness = connected();
+    let player = harness.player();
+    let start = harness.position(player);
+
+    left_stick(&mut harness, 0, 0.5, 0.);
+    harness.step(1);
+    let movement = harness
+        .movement()
+        .expect("the stick is out of its deadzone");
+    let expected = InputMap::default().stick.apply(Vec2::new(0.5, 0.)).unwrap();
+    assert_eq!(movement, expected);
+    assert!(movement.x > 0. && movement.x < 1.);
+
+    harness.step_seconds(1.);
+    let moved = harness.position(player) - start;
+    assert!(moved.x > 0. && moved.x < 120. * 0.5, "moved {:?}", moved);
+
+    left_stick(&mut harness, 0, 0.05, 0.);
+    harness.step(1);
+    assert_eq!(harness.movement(), None);
+}
+
+#[test]
+fn d_pad_moves_when_the_stick_is_centered() {
+    let mut harness = connected();
+
+    harness.gamepad_event(
+        0,
+        GamepadEventType::ButtonChanged(GamepadButtonType::DPadUp, 1.),
+    );
+    harness.step(1);
+    assert_eq!(harness.movement(), Some(Vec2::Y));
+
+    left_stick(&mut harness, 0, -1., 0.);
+    harness.step(1);
+    assert_eq!(harness.movement(), Some(-Vec2::X));
+}
+
+#[test]
+fn unplugging_the_gamepad_hands_over_to_the_next_one() {
+    let mut harness = connected();
+    harness.gamepad_event(1, GamepadEventType::Connected);
+    left_stick(&mut harness, 0, 1., 0.);
+    left_stick(&mut harness, 1, 0., -1.);
+    harness.step(1);
+    assert_eq!(harness.movement(), Some(Vec2::X));
+
+    harness.gamepad_event(0, GamepadEventType::Disconnected);
+    harness.step(1);
+    assert_eq!(harness.movement(), Some(-Vec2::Y));
+
+    harness.gamepad_event(1, GamepadEventType::Disconnected);
+    harness.step(1);
+    assert_eq!(harness.movement(), None);
+}
+
+#[test]
+fn right_stick_aims_when_manual_aim_is_on() {
+    let mut harness = connected();
+    let right_stick = |harness: &mut Harness, x, y| {
+        for (axis, value) in [
+            (GamepadAxisType::RightStickX, x),
+            (GamepadAxisType::RightStickY, y),
+        ] {
+            harness.gamepad_event(0, GamepadEventType::AxisChanged(axis, value));
+        }
+    };
+
+    right_stick(&mut harness, 0., 1.);
+    harness.step(1);
+    assert_eq!(harness.aim(), None);
+
+    harness
+        .app
+        .world
+        .get_resource_mut::<InputMap>()
+        .unwrap()
+        .aim = AimMode::Manual;
+    harness.step(1);
+    assert_eq!(harness.aim(), Some(Vec2::Y));
+
+    right_stick(&mut harness, 0., 0.);
+    harness.step(1);
+    assert_eq!(harness.aim(), None);
+}
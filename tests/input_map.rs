@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use bevy::prelude::*;
+use bevy_game::input_map::{Action, Binding, InputMap, Inputs};
+
+#[test]
+fn actions_can_be_bound_to_several_inputs() {
+    let mut map = InputMap::default();
+    map.clear(Action::MoveUp);
+    map.bind(Action::MoveUp, Binding::Key(KeyCode::Z));
+    map.bind(Action::MoveUp, Binding::Mouse(MouseButton::Right));
+    map.bind(Action::MoveUp, Binding::Key(KeyCode::Z));
+    assert_eq!(map.bindings(Action::MoveUp).len(), 2);
+
+    let mut keys = Input::<KeyCode>::default();
+    let mut mouse = Input::<MouseButton>::default();
+    let pad = Input::<GamepadButton>::default();
+    keys.press(KeyCode::W);
+    let inputs = Inputs {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad_buttons: &pad,
+        gamepad: None,
+    };
+    assert!(!map.pressed(Action::MoveUp, inputs));
+
+    keys.press(KeyCode::Z);
+    let inputs = Inputs {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad_buttons: &pad,
+        gamepad: None,
+    };
+    assert!(map.just_pressed(Action::MoveUp, inputs));
+
+    keys.clear();
+    keys.release(KeyCode::Z);
+    mouse.press(MouseButton::Right);
+    let inputs = Inputs {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad_buttons: &pad,
+        gamepad: None,
+    };
+    assert!(map.pressed(Action::MoveUp, inputs));
+    assert!(map.just_released(Action::MoveUp, inputs));
+}
+
+#[test]
+fn saved_input_maps_keep_the_default_bindings_they_miss() {
+    let path = std::env::temp_dir().join(format!("input_map-{}.ron", std::process::id()));
+    assert_eq!(InputMap::load_from(&path), InputMap::default());
+
+    // a file saved before some actions existed
+    let mut bindings = BTreeMap::new();
+    bindings.insert(Action::Dash, vec![Binding::Mouse(MouseButton::Left)]);
+    let saved = InputMap {
+        bindings,
+        ..InputMap::default()
+    };
+    saved.save_to(&path);
+    let map = InputMap::load_from(&path);
+    assert_eq!(
+        map.bindings(Action::Dash),
+        [Binding::Mouse(MouseButton::Left)]
+    );
+    assert_eq!(
+        map.bindings(Action::MoveUp),
+        InputMap::default().bindings(Action::MoveUp)
+    );
+
+    fs::write(&path, "(bindings: {Dash: [Key(").unwrap();
+    assert_eq!(InputMap::load_from(&path), InputMap::default());
+
+    fs::remove_file(&path).unwrap();
+}
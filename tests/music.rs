@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use bevy_game::music::{MusicDirector, MusicLibrary, MusicTrack, Playlist};
+use bevy_game::GameState;
+
+fn library() -> MusicLibrary {
+    MusicLibrary(HashMap::from([
+        (
+            Playlist::Playing,
+            vec![
+                MusicTrack::new("audio/calm_1.ogg", Some("audio/intense_1.ogg")),
+                MusicTrack::new("audio/calm_2.ogg", Some("audio/intense_2.ogg")),
+            ],
+        ),
+        (
+            Playlist::GameOver,
+            vec![MusicTrack::new("audio/game_over.ogg", None)],
+        ),
+    ]))
+}
+
+#[test]
+fn playlists_follow_the_game_state() {
+    assert_eq!(
+        Playlist::for_state(&GameState::Menu, false),
+        Some(Playlist::Menu)
+    );
+    assert_eq!(
+        Playlist::for_state(&GameState::Paused, false),
+        Some(Playlist::Playing)
+    );
+    assert_eq!(
+        Playlist::for_state(&GameState::Playing, true),
+        Some(Playlist::Boss)
+    );
+    assert_eq!(Playlist::for_state(&GameState::Settings, false), None);
+
+    let library = library();
+    let mut director = MusicDirector::default();
+    director.set_playlist(Playlist::Playing, &library);
+    let first = director.track().cloned();
+
+    // staying on the playlist keeps the track, coming back to it plays the next one
+    director.set_playlist(Playlist::Playing, &library);
+    assert_eq!(director.track().cloned(), first);
+    director.set_playlist(Playlist::GameOver, &library);
+    director.set_playlist(Playlist::Playing, &library);
+    assert_ne!(director.track().cloned(), first);
+}
+
+#[test]
+fn crowds_crossfade_to_the_intense_layer() {
+    let library = library();
+    let mut director = MusicDirector::default();
+    director.set_playlist(Playlist::Playing, &library);
+    assert_eq!(director.layer_volumes(), (1., 0.));
+
+    // the crossfade is gradual
+    director.update_intensity(100, 0.1);
+    let (calm, intense) = director.layer_volumes();
+    assert!(calm > intense && intense > 0.);
+
+    for _ in 0..100 {
+        director.update_intensity(100, 0.1);
+    }
+    let (calm, intense) = director.layer_volumes();
+    assert!(calm.abs() < 1e-5 && (intense - 1.).abs() < 1e-5);
+
+    for _ in 0..100 {
+        director.update_intensity(0, 0.1);
+    }
+    assert_eq!(director.intensity(), 0.);
+}
+
+#[test]
+fn default_library_only_plays_shipped_files() {
+    let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+    for path in MusicLibrary::default().paths() {
+        assert!(assets.join(path).is_file(), "missing music track {}", path);
+    }
+}
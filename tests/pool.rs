@@ -0,0 +1,25 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_game::player::XpGem;
+use bevy_game::pool::Pool;
+use common::Harness;
+
+#[test]
+fn gathered_gems_are_reused() {
+    let mut harness = Harness::new();
+    harness.disarm();
+
+    for _ in 0..2 {
+        let enemy = harness.spawn_enemy("necromancer", Vec2::new(100., 0.));
+        harness.damage(enemy, 3.);
+        // the gem is attracted by the player and gathered
+        harness.step_seconds(1.);
+    }
+
+    let pool = harness.app.world.get_resource::<Pool<XpGem>>().unwrap();
+    assert_eq!(pool.spawned(), 1);
+    assert_eq!(pool.reused(), 1);
+    assert_eq!(pool.free(), 1);
+    assert_eq!(pool.active(), 0);
+}
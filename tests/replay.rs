@@ -0,0 +1,162 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use bevy_game::enemy::Enemy;
+use bevy_game::player::MainCamera;
+use bevy_game::progression::XpGainedEvent;
+use bevy_game::replay::{Replay, ReplayPlayer, ReplayRecorder};
+use bevy_game::waves::{Formation, Repeat, Wave};
+use bevy_game::GameState;
+use common::Harness;
+
+fn waves() -> Vec<Wave> {
+    vec![Wave {
+        at: 1.,
+        archetype: "necromancer".to_string(),
+        count: 3,
+        formation: Formation::Ring,
+        radius: 150.,
+        repeat: Some(Repeat {
+            every: 3.,
+            times: 10,
+        }),
+        boss: false,
+    }]
+}
+
+/// Positions of the player and of every enemy, in spawn order
+fn snapshot(harness: &mut Harness) -> Vec<Vec2> {
+    let player = harness.player();
+    let mut positions = vec![harness.position(player)];
+    let mut enemies: Vec<Entity> = harness
+        .app
+        .world
+        .query_filtered::<Entity, With<Enemy>>()
+        .iter(&harness.app.world)
+        .collect();
+    enemies.sort();
+    positions.extend(enemies.into_iter().map(|e| harness.position(e)));
+    positions
+}
+
+#[test]
+fn replay_reproduces_the_recorded_run() {
+    let mut recorded = Harness::with_waves(waves());
+    recorded.app.insert_resource(ReplayRecorder {
+        path: PathBuf::new(),
+        replay: Replay::new(common::SEED),
+    });
+    for (seconds, movement) in [
+        (2., Some(Vec2::X)),
+        (1.5, None),
+        (3., Some(Vec2::new(-1., 1.).normalize())),
+        (4., Some(Vec2::Y)),
+    ] {
+        recorded.set_movement(movement);
+        recorded.step_seconds(seconds);
+    }
+    let replay = recorded
+        .app
+        .world
+        .get_resource::<ReplayRecorder>()
+        .unwrap()
+        .replay
+        .clone();
+    assert_eq!(replay.movement.len(), 4);
+    assert_eq!(replay.ticks(), 630);
+
+    let mut replayed = Harness::with_waves(waves());
+    replayed.app.insert_resource(ReplayPlayer::new(replay));
+    replayed.step(630);
+
+    assert_eq!(snapshot(&mut recorded), snapshot(&mut replayed));
+    let (recorded_player, replayed_player) = (recorded.player(), replayed.player());
+    assert_eq!(
+        recorded.health(recorded_player),
+        replayed.health(replayed_player)
+    );
+}
+
+#[test]
+fn waves_spawn_around_the_player_wherever_the_camera_is() {
+    // the camera follows the interpolated player and shakes, which depends on the frame timing,
+    // so a wave spawned around it would not play back the same
+    let mut harness = Harness::with_waves(waves());
+    let player = harness.player();
+    let spawned = harness.step_until(2., |harness| {
+        let mut cameras = harness
+            .app
+            .world
+            .query_filtered::<&mut Transform, With<MainCamera>>();
+        for mut camera in cameras.iter_mut(&mut harness.app.world) {
+            camera.translation.x += 1000.;
+        }
+        harness
+            .app
+            .world
+            .query_filtered::<(), With<Enemy>>()
+            .iter(&harness.app.world)
+            .count()
+            > 0
+    });
+    assert!(spawned.is_some());
+
+    let center = harness.position(player);
+    for p in snapshot(&mut harness).into_iter().skip(1) {
+        assert!((p.distance(center) - 150.).abs() < 5.);
+    }
+}
+
+#[test]
+fn player_picks_the_upgrades_the_replay_did_not_record() {
+    let mut harness = Harness::new();
+    harness
+        .app
+        .insert_resource(ReplayPlayer::new(Replay::new(common::SEED)));
+    let player = harness.player();
+    harness
+        .app
+        .world
+        .get_resource_mut::<Events<XpGainedEvent>>()
+        .unwrap()
+        .send(XpGainedEvent {
+            entity: player,
+            amount: 5,
+        });
+    harness.step(3);
+    assert_eq!(harness.state(), GameState::LevelUp);
+
+    harness.tap_key(KeyCode::Return);
+    harness.step(1);
+    assert_eq!(harness.state(), GameState::Playing);
+}
+
+#[test]
+fn recorded_runs_are_saved_when_they_end() {
+    let path = std::env::temp_dir().join(format!("replay-{}.ron", std::process::id()));
+    let mut harness = Harness::new();
+    harness.app.insert_resource(ReplayRecorder {
+        path: path.clone(),
+        replay: Replay::new(0),
+    });
+    harness.set_movement(Some(Vec2::X));
+    harness.step(10);
+
+    let player = harness.player();
+    harness.damage(player, 1000.);
+    harness.step(3);
+    assert_eq!(harness.state(), GameState::GameOver);
+
+    let replay = Replay::load(&path).unwrap();
+    assert_eq!(replay.seed, common::SEED);
+    assert_eq!(replay.movement[0].movement, Some((1., 0.)));
+    assert!(replay.ticks() >= 10);
+
+    fs::write(&path, "(version: \"0.1.0\", seed: ").unwrap();
+    assert!(Replay::load(&path).is_err());
+
+    fs::remove_file(&path).unwrap();
+}
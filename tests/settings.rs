@@ -0,0 +1,71 @@
+mod common;
+
+use std::fs;
+
+use bevy::prelude::*;
+use bevy_game::settings::{SettingOption, Settings, WindowModeSetting};
+use bevy_game::GameState;
+use common::Harness;
+
+#[test]
+fn options_cycle_and_clamp() {
+    let mut settings = Settings::default();
+
+    SettingOption::WindowMode.adjust(&mut settings, -1);
+    assert_eq!(settings.window_mode, WindowModeSetting::Fullscreen);
+    SettingOption::WindowMode.adjust(&mut settings, 1);
+    assert_eq!(settings.window_mode, WindowModeSetting::Windowed);
+
+    for _ in 0..20 {
+        SettingOption::MasterVolume.adjust(&mut settings, 1);
+        SettingOption::PixelScale.adjust(&mut settings, -1);
+    }
+    assert_eq!(settings.master_volume, 1.);
+    assert_eq!(settings.pixel_scale, 1.);
+
+    SettingOption::MasterVolume.adjust(&mut settings, -1);
+    assert!((settings.music_volume() - 0.9 * settings.music_volume).abs() < 1e-5);
+
+    SettingOption::DamageNumbers.adjust(&mut settings, 1);
+    assert!(!settings.damage_numbers);
+}
+
+#[test]
+fn broken_settings_files_fall_back_to_the_defaults() {
+    let path = std::env::temp_dir().join(format!("settings-{}.ron", std::process::id()));
+    assert_eq!(Settings::load_from(&path), Settings::default());
+
+    let mut settings = Settings::default();
+    SettingOption::WindowSize.adjust(&mut settings, 1);
+    SettingOption::ScreenShake.adjust(&mut settings, 1);
+    settings.save_to(&path);
+    assert_eq!(Settings::load_from(&path), settings);
+
+    fs::write(&path, "(window_width: \"wide\"").unwrap();
+    assert_eq!(Settings::load_from(&path), Settings::default());
+
+    // fields missing from an older file keep their default
+    fs::write(&path, "(vsync: false)").unwrap();
+    let partial = Settings::load_from(&path);
+    assert!(!partial.vsync);
+    assert_eq!(partial.pixel_scale, Settings::default().pixel_scale);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn settings_opened_from_the_pause_menu_close_with_cancel() {
+    let mut harness = Harness::new();
+    harness.disarm();
+    harness.tap_key(KeyCode::Escape);
+    assert_eq!(harness.state(), GameState::Paused);
+
+    // the second button of the pause menu
+    harness.tap_key(KeyCode::Down);
+    harness.tap_key(KeyCode::Return);
+    assert_eq!(harness.state(), GameState::Settings);
+
+    // only closes the settings, the run stays paused under them
+    harness.tap_key(KeyCode::Escape);
+    assert_eq!(harness.state(), GameState::Paused);
+}
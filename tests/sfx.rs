@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use bevy_game::sfx::{Sfx, SfxMap, SfxSound, SfxThrottle};
+
+fn sound(paths: &[&str], max_instances: usize, min_interval: f64, duration: f64) -> SfxSound {
+    SfxSound {
+        paths: paths.iter().map(|path| path.to_string()).collect(),
+        max_instances,
+        min_interval,
+        duration,
+    }
+}
+
+#[test]
+fn simultaneous_hits_are_throttled() {
+    let map = SfxMap(HashMap::from([
+        (Sfx::Hit, sound(&["audio/hit.ogg"], 4, 0.05, 0.2)),
+        (
+            Sfx::EnemyDeath,
+            sound(&["audio/enemy_death.ogg"], 4, 0.05, 0.4),
+        ),
+    ]));
+    let mut throttle = SfxThrottle::default();
+
+    let played = (0..200)
+        .filter(|_| throttle.select(&map, Sfx::Hit, 0.).is_some())
+        .count();
+    assert_eq!(played, 1);
+
+    // other sounds aren't limited by the hits
+    assert!(throttle.select(&map, Sfx::EnemyDeath, 0.).is_some());
+}
+
+#[test]
+fn concurrent_instances_are_limited() {
+    let map = SfxMap(HashMap::from([(
+        Sfx::Hit,
+        sound(&["audio/hit.ogg"], 2, 0., 1.),
+    )]));
+    let mut throttle = SfxThrottle::default();
+
+    assert!(throttle.select(&map, Sfx::Hit, 0.).is_some());
+    assert!(throttle.select(&map, Sfx::Hit, 0.5).is_some());
+    assert!(throttle.select(&map, Sfx::Hit, 0.75).is_none());
+    // the first instance is over
+    assert!(throttle.select(&map, Sfx::Hit, 1.).is_some());
+    // effects without sounds are silent
+    assert!(throttle.select(&map, Sfx::LevelUp, 0.).is_none());
+}
+
+#[test]
+fn variations_play_in_turn() {
+    let map = SfxMap(HashMap::from([(
+        Sfx::Hit,
+        sound(&["audio/hit_1.ogg", "audio/hit_2.ogg"], 4, 0.05, 0.2),
+    )]));
+    let mut throttle = SfxThrottle::default();
+
+    let first = throttle.select(&map, Sfx::Hit, 0.).unwrap();
+    let second = throttle.select(&map, Sfx::Hit, 1.).unwrap();
+    let third = throttle.select(&map, Sfx::Hit, 2.).unwrap();
+    assert_ne!(first, second);
+    assert_eq!(first, third);
+}
+
+#[test]
+fn default_effects_only_play_shipped_files() {
+    let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+    for path in SfxMap::default().paths() {
+        assert!(assets.join(path).is_file(), "missing sound effect {}", path);
+    }
+}
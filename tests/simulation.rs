@@ -0,0 +1,236 @@
+mod common;
+
+use benimator::SpriteSheetAnimation;
+use bevy::prelude::*;
+use bevy_game::animation::AnimationLibrary;
+use bevy_game::enemy::{Alive, Dead};
+use bevy_game::game_over::RunStats;
+use bevy_game::player::PickupRadius;
+use bevy_game::progression::{Experience, XpGainedEvent};
+use bevy_game::timestep::{SimMode, SimTime};
+use bevy_game::waves::{Formation, Wave};
+use bevy_game::weapon::WeaponKind;
+use bevy_game::GameState;
+use common::Harness;
+
+#[test]
+fn player_walks_at_base_speed() {
+    let mut harness = Harness::new();
+    let player = harness.player();
+    let start = harness.position(player);
+
+    harness.set_movement(Some(Vec2::X));
+    harness.step_seconds(1.);
+
+    let moved = harness.position(player) - start;
+    assert!((moved.x - 120.).abs() < 1., "moved {:?}", moved);
+    assert!(moved.y.abs() < 0.001);
+}
+
+#[test]
+fn enemy_with_3_health_dies_after_3_hits() {
+    let mut harness = Harness::new();
+    harness.disarm();
+    let enemy = harness.spawn_enemy("necromancer", Vec2::new(250., 0.));
+
+    for hit in 1..=2 {
+        harness.damage(enemy, 1.);
+        harness.step(1);
+        assert_eq!(harness.health(enemy), 3. - hit as f32);
+        assert!(harness.has::<Alive>(enemy));
+    }
+
+    harness.damage(enemy, 1.);
+    harness.step(2);
+    assert!(harness.has::<Dead>(enemy));
+    assert!(!harness.has::<Alive>(enemy));
+}
+
+#[test]
+fn player_standing_still_next_to_an_enemy_dies() {
+    let mut harness = Harness::new();
+    harness.disarm();
+    harness.spawn_enemy("necromancer", Vec2::new(10., 0.));
+
+    // 100 health, one damage every 0.2 seconds of invincibility
+    let died_after = harness
+        .step_until(30., |harness| harness.state() == GameState::GameOver)
+        .expect("the player survived");
+    assert!(died_after > 19., "died after {} seconds", died_after);
+
+    let stats = harness.app.world.get_resource::<RunStats>().unwrap();
+    assert!((stats.time - died_after).abs() < 0.1);
+}
+
+#[test]
+fn dying_on_a_level_up_still_ends_the_run() {
+    let mut harness = Harness::new();
+    harness.disarm();
+    let player = harness.player();
+
+    // the killing blow and enough XP for a level-up land on the same tick
+    harness.damage(player, 1000.);
+    harness
+        .app
+        .world
+        .get_resource_mut::<Events<XpGainedEvent>>()
+        .unwrap()
+        .send(XpGainedEvent {
+            entity: player,
+            amount: 100,
+        });
+    harness.step(3);
+
+    assert_eq!(harness.state(), GameState::GameOver);
+}
+
+#[test]
+fn a_level_up_stops_the_ticks_left_in_its_frame() {
+    let mut harness = Harness::new();
+    harness.disarm();
+    harness
+        .app
+        .world
+        .get_resource_mut::<SimTime>()
+        .unwrap()
+        .mode = SimMode::Manual(8);
+    let player = harness.player();
+
+    harness
+        .app
+        .world
+        .get_resource_mut::<Events<XpGainedEvent>>()
+        .unwrap()
+        .send(XpGainedEvent {
+            entity: player,
+            amount: 5,
+        });
+    let before = harness.app.world.get_resource::<SimTime>().unwrap().ticks();
+    harness.app.update();
+    let ticks = harness.app.world.get_resource::<SimTime>().unwrap().ticks() - before;
+    assert!(
+        ticks < 8,
+        "{} ticks ran in the frame of the level-up",
+        ticks
+    );
+
+    // the transition is applied at the start of the next frame
+    harness.app.update();
+    assert_eq!(harness.state(), GameState::LevelUp);
+}
+
+#[test]
+fn knives_are_thrown_where_the_player_aims() {
+    let mut harness = Harness::new();
+    harness.disarm();
+    harness.equip(WeaponKind::Knife);
+    // the player faces right, the enemy is on the left
+    let enemy = harness.spawn_enemy("necromancer", Vec2::new(-150., 0.));
+
+    harness.step_seconds(1.5);
+    assert_eq!(harness.health(enemy), 3.);
+
+    harness.set_aim(Some(-Vec2::X));
+    harness.step_seconds(1.5);
+    assert!(harness.health(enemy) < 3.);
+}
+
+#[test]
+fn weapon_effects_share_their_animations() {
+    let mut harness = Harness::new();
+    harness.equip(WeaponKind::Aura);
+    harness.equip(WeaponKind::Whip);
+    for i in 0..10 {
+        harness.spawn_enemy("necromancer", Vec2::new(30. + i as f32, 0.));
+    }
+    harness.step_seconds(5.);
+
+    let library = harness
+        .app
+        .world
+        .get_resource::<AnimationLibrary>()
+        .unwrap();
+    assert!(!library.is_empty());
+    let animations = harness
+        .app
+        .world
+        .get_resource::<Assets<SpriteSheetAnimation>>()
+        .unwrap();
+    assert_eq!(animations.len(), library.len());
+}
+
+#[test]
+fn pausing_freezes_the_run_until_it_resumes() {
+    let mut harness = Harness::new();
+    harness.disarm();
+    let player = harness.player();
+    let enemy = harness.spawn_enemy("necromancer", Vec2::new(200., 0.));
+    harness.set_movement(Some(Vec2::X));
+    harness.step_seconds(0.5);
+
+    harness.tap_key(KeyCode::Escape);
+    assert_eq!(harness.state(), GameState::Paused);
+    let paused_at = (harness.position(player), harness.position(enemy));
+    let run_time = harness.app.world.get_resource::<RunStats>().unwrap().time;
+
+    harness.step_seconds(5.);
+    assert_eq!(harness.state(), GameState::Paused);
+    assert_eq!(
+        (harness.position(player), harness.position(enemy)),
+        paused_at
+    );
+    let stats = harness.app.world.get_resource::<RunStats>().unwrap();
+    assert_eq!(stats.time, run_time);
+
+    harness.tap_key(KeyCode::Escape);
+    assert_eq!(harness.state(), GameState::Playing);
+    harness.step_seconds(0.5);
+    assert!(harness.position(player).x > paused_at.0.x);
+    assert!(harness.health(player) > 0.);
+}
+
+#[test]
+fn gems_are_attracted_within_the_pickup_radius() {
+    let mut harness = Harness::new();
+    harness.disarm();
+    let player = harness.player();
+    let enemy = harness.spawn_enemy("necromancer", Vec2::new(200., 0.));
+    harness.damage(enemy, 3.);
+
+    harness.step_seconds(1.);
+    assert_eq!(harness.app.world.get::<Experience>(player).unwrap().0, 0);
+
+    harness.app.world.get_mut::<PickupRadius>(player).unwrap().0 = 250.;
+    harness.step_seconds(1.);
+    assert!(harness.app.world.get::<Experience>(player).unwrap().0 > 0);
+}
+
+#[test]
+fn waves_spawn_on_schedule() {
+    let mut harness = Harness::with_waves(vec![Wave {
+        at: 1.,
+        archetype: "necromancer".to_string(),
+        count: 4,
+        formation: Formation::Surround,
+        radius: 300.,
+        repeat: None,
+        boss: false,
+    }]);
+    harness.disarm();
+
+    harness.step_seconds(0.9);
+    assert_eq!(alive_enemies(&mut harness), 0);
+    harness.step_seconds(0.2);
+    assert_eq!(alive_enemies(&mut harness), 4);
+    harness.step_seconds(10.);
+    assert_eq!(alive_enemies(&mut harness), 4);
+}
+
+fn alive_enemies(harness: &mut Harness) -> usize {
+    harness
+        .app
+        .world
+        .query_filtered::<(), With<Alive>>()
+        .iter(&harness.app.world)
+        .count()
+}